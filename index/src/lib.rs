@@ -25,6 +25,15 @@ impl<T> rkyv::Archive for Index<T> {
 	}
 }
 
+unsafe impl<C: rkyv::rancor::Fallible + ?Sized, T> rkyv::bytecheck::CheckBytes<C> for ArchivedIndex<T> where
+	<u64 as rkyv::Archive>::Archived: rkyv::bytecheck::CheckBytes<C>,
+{
+	#[inline]
+	unsafe fn check_bytes(value: *const Self, context: &mut C) -> Result<(), C::Error> {
+		<<u64 as rkyv::Archive>::Archived as rkyv::bytecheck::CheckBytes<C>>::check_bytes(value.cast(), context)
+	}
+}
+
 impl<D: rkyv::rancor::Fallible + ?Sized, T> rkyv::Deserialize<Index<T>, D> for ArchivedIndex<T> {
 	#[inline]
 	fn deserialize(&self, deserializer: &mut D) -> Result<Index<T>, D::Error> {
@@ -51,6 +60,10 @@ impl<T> From<Index<T>> for u64 {
 	fn from(value: Index<T>) -> Self { value.0 }
 }
 
+impl<T> From<&ArchivedIndex<T>> for Index<T> {
+	fn from(value: &ArchivedIndex<T>) -> Self { Self(value.0.to_native(), PhantomData) }
+}
+
 impl<T> From<u64> for Index<T> {
 	fn from(value: u64) -> Self { Self(value, PhantomData) }
 }
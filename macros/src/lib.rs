@@ -2,7 +2,7 @@
 
 use proc_quote::quote;
 
-#[proc_macro_derive(DbName, attributes(name, flags, table))]
+#[proc_macro_derive(DbName, attributes(name, flags, table, indices))]
 pub fn derive_db_name(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 	let input = syn::parse_macro_input!(input as syn::DeriveInput);
 	let name = &input.ident;
@@ -12,6 +12,7 @@ pub fn derive_db_name(input: proc_macro::TokenStream) -> proc_macro::TokenStream
 	let mut db_name = None;
 	let mut flags = None;
 	let mut table = None;
+	let mut indices = Vec::new();
 	for attr in input.attrs {
 		let args = attr.meta.require_list().unwrap();
 		match &attr.path().get_ident().unwrap().to_string() as &str {
@@ -21,6 +22,7 @@ pub fn derive_db_name(input: proc_macro::TokenStream) -> proc_macro::TokenStream
 			},
 			"flags" => { flags = Some(args.parse_args::<syn::Expr>().unwrap()); },
 			"table" => { table = Some(args.parse_args::<syn::Type>().unwrap()); },
+			"indices" => { indices.extend(args.parse_args_with(syn::punctuated::Punctuated::<syn::Type, syn::Token![,]>::parse_terminated).unwrap()); },
 			_ => unreachable!(),
 		}
 	}
@@ -28,12 +30,44 @@ pub fn derive_db_name(input: proc_macro::TokenStream) -> proc_macro::TokenStream
 	let flags = flags.map_or_else(|| quote!(), |x| quote!(fn flags() -> #crate_name::enumflags2::BitFlags<#crate_name::lmdb::DbFlags> { #x.into() }));
 	let db_name = db_name.map_or_else(|| quote!(&::std::concat!(::std::module_path!(), "::", ::std::stringify!(#name), "\0").as_bytes()), |x| quote!(#x));//syn::LitByteStr::new(format!("{}\0", name).as_bytes(), name.span()));
 
+	let indices = if indices.is_empty() { quote!() } else { quote!(
+		impl<T: #crate_name::rkyv::Archive> #crate_name::secondary_index::SecondaryIndices<T> for #name where
+			#(#indices: #crate_name::secondary_index::SecondaryIndex<T>,)*
+		{
+			fn dbs() -> ::std::vec::Vec<(&'static [u8], #crate_name::enumflags2::BitFlags<#crate_name::lmdb::DbFlags>)> {
+				::std::vec![#(#crate_name::secondary_index::db::<#indices, T>(),)*]
+			}
+
+			fn check<'tx>(tx: &'tx #crate_name::RwTxn<'tx>, index: #crate_name::Index<T>, t: &#crate_name::rkyv::Archived<T>) -> ::std::result::Result<(), #crate_name::Error> where T: 'tx {
+				#(#crate_name::secondary_index::check::<#indices, T>(tx, index, t)?;)*
+				::std::result::Result::Ok(())
+			}
+
+			fn insert<'tx>(tx: &'tx #crate_name::RwTxn<'tx>, index: #crate_name::Index<T>, t: &#crate_name::rkyv::Archived<T>) -> ::std::result::Result<(), #crate_name::Error> where T: 'tx {
+				#(#crate_name::secondary_index::insert::<#indices, T>(tx, index, t)?;)*
+				::std::result::Result::Ok(())
+			}
+
+			fn remove<'tx>(tx: &'tx #crate_name::RwTxn<'tx>, index: #crate_name::Index<T>, t: &#crate_name::rkyv::Archived<T>) -> ::std::result::Result<(), #crate_name::Error> where T: 'tx {
+				#(#crate_name::secondary_index::remove::<#indices, T>(tx, index, t)?;)*
+				::std::result::Result::Ok(())
+			}
+
+			fn clear<'tx>(tx: &'tx #crate_name::RwTxn<'tx>) -> ::std::result::Result<(), #crate_name::Error> where T: 'tx {
+				#(#crate_name::secondary_index::clear::<#indices, T>(tx)?;)*
+				::std::result::Result::Ok(())
+			}
+		}
+	) };
+
 	quote!(
 		impl #crate_name::DbName for #name {
 			type Table<'tx, 'env: 'tx, TX: #crate_name::Transaction<'env> + 'tx> = #table;
 			const NAME: &'static [u8] = #db_name;
 			#flags
 		}
+
+		#indices
 	).into()
 }
 
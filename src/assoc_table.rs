@@ -1,6 +1,7 @@
 use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb};
 use culpa::throws;
 use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
 
 pub struct AssocTable<'tx, TX, K, V> {
 	tx: &'tx TX,
@@ -22,25 +23,33 @@ impl<'tx, 'env: 'tx, TX, K, V> Table<'tx, 'env, TX> for AssocTable<'tx, TX, K, V
 	}
 }
 
-fn archived_from_cursor_get<'tx, K, V>(get: Option<(&'tx [u8], &'tx [u8])>) -> Option<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> where
+#[throws]
+pub(crate) fn archived_from_cursor_get<'tx, K, V>((key_bytes, value_bytes): (&'tx [u8], &'tx [u8])) -> (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>) where
 	K: rkyv::Archive,
 	V: rkyv::Archive,
 	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 	rkyv::Archived<V>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 {
-	let (key_bytes, value_bytes) = get?;
-	let key = match rkyv::access::<rkyv::Archived<K>, _>(key_bytes) {
-		Ok(x) => x,
-		Err(e) => { log::error!("Error deserializing key in cursor: {e:?}"); return None; }
-	};
-	let value = match rkyv::access::<rkyv::Archived<V>, _>(value_bytes) {
-		Ok(x) => x,
-		Err(e) => { log::error!("Error deserializing value in cursor: {e:?}"); return None; }
-	};
-	Some((key, value))
+	(
+		rkyv::access::<rkyv::Archived<K>, _>(key_bytes)?,
+		rkyv::access::<rkyv::Archived<V>, _>(value_bytes)?,
+	)
+}
+
+// stops after the first error
+pub(crate) struct Cursor<'tx, TX, K, V> {
+	cursor: lmdb::Cursor<'tx, TX>,
+	op: lmdb::CursorOpFlags,
+	done: bool,
+	_pd: PhantomData<(K, V)>,
+}
+
+impl<'tx, TX, K, V> Cursor<'tx, TX, K, V> {
+	pub(crate) fn new(cursor: lmdb::Cursor<'tx, TX>, op: lmdb::CursorOpFlags) -> Self {
+		Self { cursor, op, done: false, _pd: PhantomData }
+	}
 }
 
-struct Cursor<'tx, TX, K, V>(lmdb::Cursor<'tx, TX>, lmdb::CursorOpFlags, PhantomData<(K, V)>);
 impl<'tx, 'env: 'tx, TX, K, V> Iterator for Cursor<'tx, TX, K, V> where
 	TX: Transaction<'env>,
 	K: rkyv::Archive,
@@ -48,10 +57,16 @@ impl<'tx, 'env: 'tx, TX, K, V> Iterator for Cursor<'tx, TX, K, V> where
 	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 	rkyv::Archived<V>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 {
-	type Item = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>);
+	type Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>;
 
-	fn next(&mut self) -> Option<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> {
-		archived_from_cursor_get::<'tx, K, V>(self.0.get(self.1))
+	fn next(&mut self) -> Option<Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> {
+		if self.done { return None; }
+		let res = match self.cursor.get(self.op) {
+			Ok(get) => get.map(archived_from_cursor_get::<'tx, K, V>),
+			Err(e) => Some(Err(e.into())),
+		};
+		self.done = !matches!(res, Some(Ok(_)));
+		res
 	}
 }
 
@@ -104,49 +119,322 @@ impl<'tx, 'env: 'tx, TX, K, V> AssocTable<'tx, TX, K, V> where
 
 	#[throws]
 	pub fn last(&self) -> Option<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> {
-		let Some((key_bytes, value_bytes)) = lmdb::Cursor::open(self.tx, self.dbi)?.get(lmdb::CursorOpFlags::Last) else { return None; };
-		Some((
-			rkyv::access::<rkyv::Archived<K>, _>(key_bytes)?,
-			rkyv::access::<rkyv::Archived<V>, _>(value_bytes)?,
-		))
+		let Some(get) = lmdb::Cursor::open(self.tx, self.dbi)?.get(lmdb::CursorOpFlags::Last)? else { return None; };
+		Some(archived_from_cursor_get::<'tx, K, V>(get)?)
+	}
+
+	/// double-ended, so `.rev()` and `.next_back()` work too
+	/// keys are in the db's order, i.e. the bytewise order of their archived form,
+	/// which isn't necessarily `K`'s `Ord`, e.g. archived integers are little-endian
+	/// stops after the first error
+	#[throws]
+	pub fn range<R: RangeBounds<K>>(&self, range: R) -> impl DoubleEndedIterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V, R> where
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{
+		self.range_bytes(bound_bytes(range.start_bound())?, bound_bytes(range.end_bound())?)?
+	}
+
+	// takes serialized bounds so the returned iterator doesn't borrow the keys
+	#[throws]
+	fn range_bytes(&self, start: Bound<rkyv::util::AlignedVec>, end: Bound<rkyv::util::AlignedVec>) -> Range<'tx, TX, K, V> {
+		Range {
+			tx: self.tx,
+			dbi: self.dbi,
+			front: lmdb::Cursor::open(self.tx, self.dbi)?,
+			back: lmdb::Cursor::open(self.tx, self.dbi)?,
+			start,
+			end,
+			front_key: None,
+			back_key: None,
+			done: false,
+			_pd: PhantomData,
+		}
 	}
 
+	/// same as `range(..)`
 	#[expect(clippy::iter_not_returning_iterator)]
 	#[throws]
-	pub fn iter(&self) -> impl Iterator<Item = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> + use<'tx, 'env, TX, K, V> where
+	pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V> where
 		rkyv::Archived<K>: 'tx,
 		rkyv::Archived<V>: 'tx,
 	{
-		Cursor::<TX, K, V>(lmdb::Cursor::open(self.tx, self.dbi)?, lmdb::CursorOpFlags::Next, PhantomData)
+		self.range_bytes(Bound::Unbounded, Bound::Unbounded)?
 	}
 
+	/// same as `range(key..)`, starts at `key` or the first key after it
 	#[throws]
-	pub fn iter_from(&self, key: &K) -> impl Iterator<Item = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> + use<'tx, 'env, TX, K, V> where
+	pub fn iter_from(&self, key: &K) -> impl DoubleEndedIterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V> where
 		rkyv::Archived<K>: 'tx,
 		rkyv::Archived<V>: 'tx,
 	{
-		let mut key_bytes = rkyv::to_bytes(key)?;
-		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
-		archived_from_cursor_get::<'tx, K, V>(cursor.get_with_key(&mut key_bytes, lmdb::CursorOpFlags::SetRange)).into_iter()
-			.chain(Cursor::<TX, K, V>(cursor, lmdb::CursorOpFlags::Next, PhantomData))
+		self.range_bytes(Bound::Included(rkyv::to_bytes(key)?), Bound::Unbounded)?
 	}
 
+	/// same as `range(..).rev()`
 	#[throws]
-	pub fn iter_rev(&self) -> impl Iterator<Item = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> + use<'tx, 'env, TX, K, V> where
+	pub fn iter_rev(&self) -> impl DoubleEndedIterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V> where
 		rkyv::Archived<K>: 'tx,
 		rkyv::Archived<V>: 'tx,
 	{
-		Cursor::<TX, K, V>(lmdb::Cursor::open(self.tx, self.dbi)?, lmdb::CursorOpFlags::Prev, PhantomData)
+		self.range_bytes(Bound::Unbounded, Bound::Unbounded)?.rev()
 	}
 
+	/// same as `range(..=key).rev()`, starts at `key` or the last key before it
 	#[throws]
-	pub fn iter_rev_from(&self, key: &K) -> impl Iterator<Item = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> + use<'tx, 'env, TX, K, V> where
+	pub fn iter_rev_from(&self, key: &K) -> impl DoubleEndedIterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V> where
 		rkyv::Archived<K>: 'tx,
 		rkyv::Archived<V>: 'tx,
 	{
-		let mut key_bytes = rkyv::to_bytes(key)?;
-		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
-		let _ = cursor.get_with_key(&mut key_bytes, lmdb::CursorOpFlags::SetRange);
-		Cursor::<TX, K, V>(cursor, lmdb::CursorOpFlags::Prev, PhantomData)
+		self.range_bytes(Bound::Unbounded, Bound::Included(rkyv::to_bytes(key)?))?.rev()
+	}
+}
+
+#[throws]
+fn bound_bytes<K: for <'a> rkyv::Serialize<RkyvSer<'a>>>(bound: Bound<&K>) -> Bound<rkyv::util::AlignedVec> {
+	match bound {
+		Bound::Included(key) => Bound::Included(rkyv::to_bytes(key)?),
+		Bound::Excluded(key) => Bound::Excluded(rkyv::to_bytes(key)?),
+		Bound::Unbounded => Bound::Unbounded,
+	}
+}
+
+type KeyValueBytes<'tx> = (&'tx [u8], &'tx [u8]);
+
+// reuses the buffer of the last key
+fn remember(last: &mut Option<Vec<u8>>, key: Option<&[u8]>) {
+	match key {
+		Some(key) => {
+			let last = last.get_or_insert_with(Vec::new);
+			last.clear();
+			last.extend_from_slice(key);
+		},
+		None => *last = None,
+	}
+}
+
+// separate cursors for both ends, each end stops at its bound or at the last key yielded by the other end
+// stops after the first error
+struct Range<'tx, TX, K, V> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	front: lmdb::Cursor<'tx, TX>,
+	back: lmdb::Cursor<'tx, TX>,
+	start: Bound<rkyv::util::AlignedVec>,
+	end: Bound<rkyv::util::AlignedVec>,
+	// copied because returned pointers don't survive writes to the tx
+	front_key: Option<Vec<u8>>,
+	back_key: Option<Vec<u8>>,
+	done: bool,
+	_pd: PhantomData<(K, V)>,
+}
+
+impl<'tx, 'env: 'tx, TX, K, V> Range<'tx, TX, K, V> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	fn cmp(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering { lmdb::cmp(self.tx, self.dbi, a, b) }
+
+	fn in_range(&self, key: &[u8]) -> bool {
+		let after_start = match &self.start {
+			Bound::Included(start) => self.cmp(key, start).is_ge(),
+			Bound::Excluded(start) => self.cmp(key, start).is_gt(),
+			Bound::Unbounded => true,
+		};
+		let before_end = match &self.end {
+			Bound::Included(end) => self.cmp(key, end).is_le(),
+			Bound::Excluded(end) => self.cmp(key, end).is_lt(),
+			Bound::Unbounded => true,
+		};
+		after_start && before_end
+			&& self.front_key.as_deref().is_none_or(|front_key| self.cmp(key, front_key).is_gt())
+			&& self.back_key.as_deref().is_none_or(|back_key| self.cmp(key, back_key).is_lt())
+	}
+
+	fn yielded(&mut self, get: Result<Option<KeyValueBytes<'tx>>, lmdb::Error>) -> Option<<Self as Iterator>::Item> {
+		let get = match get {
+			Ok(Some(get)) if self.in_range(get.0) => get,
+			Ok(_) => { self.done = true; return None; },
+			Err(e) => { self.done = true; return Some(Err(e.into())); },
+		};
+		let res = archived_from_cursor_get::<'tx, K, V>(get);
+		if res.is_err() { self.done = true; }
+		Some(res)
+	}
+}
+
+impl<'tx, 'env: 'tx, TX, K, V> Iterator for Range<'tx, TX, K, V> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	type Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>;
+
+	fn next(&mut self) -> Option<Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> {
+		if self.done { return None; }
+		let get = if self.front_key.is_some() {
+			self.front.get(lmdb::CursorOpFlags::Next)
+		} else {
+			match &mut self.start {
+				Bound::Included(start) => self.front.get_with_key(start, lmdb::CursorOpFlags::SetRange),
+				// SetRange finds the first key >= start, which has to be skipped if it's start itself
+				Bound::Excluded(start) => match self.front.get_with_key(start, lmdb::CursorOpFlags::SetRange) {
+					Ok(Some((key, _))) if lmdb::cmp(self.tx, self.dbi, key, start).is_eq() => self.front.get(lmdb::CursorOpFlags::Next),
+					get => get,
+				},
+				Bound::Unbounded => self.front.get(lmdb::CursorOpFlags::First),
+			}
+		};
+		let key = get.as_ref().ok().and_then(|get| get.map(|(key, _)| key));
+		let item = self.yielded(get)?;
+		remember(&mut self.front_key, key);
+		Some(item)
+	}
+}
+
+impl<'tx, 'env: 'tx, TX, K, V> DoubleEndedIterator for Range<'tx, TX, K, V> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	fn next_back(&mut self) -> Option<Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> {
+		if self.done { return None; }
+		let get = if self.back_key.is_some() {
+			self.back.get(lmdb::CursorOpFlags::Prev)
+		} else {
+			match &mut self.end {
+				// SetRange finds the first key >= end, which is one too far unless it's end itself
+				Bound::Included(end) => match self.back.get_with_key(end, lmdb::CursorOpFlags::SetRange) {
+					Ok(Some((key, value))) if lmdb::cmp(self.tx, self.dbi, key, end).is_eq() => Ok(Some((key, value))),
+					Ok(Some(_)) => self.back.get(lmdb::CursorOpFlags::Prev),
+					Ok(None) => self.back.get(lmdb::CursorOpFlags::Last),
+					Err(e) => Err(e),
+				},
+				Bound::Excluded(end) => match self.back.get_with_key(end, lmdb::CursorOpFlags::SetRange) {
+					Ok(Some(_)) => self.back.get(lmdb::CursorOpFlags::Prev),
+					Ok(None) => self.back.get(lmdb::CursorOpFlags::Last),
+					Err(e) => Err(e),
+				},
+				Bound::Unbounded => self.back.get(lmdb::CursorOpFlags::Last),
+			}
+		};
+		let key = get.as_ref().ok().and_then(|get| get.map(|(key, _)| key));
+		let item = self.yielded(get)?;
+		remember(&mut self.back_key, key);
+		Some(item)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, AssocTable, Error, test_env};
+	use std::ops::Bound;
+
+	// u8 keys, so the archived bytes sort like the numbers
+	#[derive(DbName)]
+	#[table(AssocTable<'tx, TX, u8, u8>)]
+	struct Evens;
+
+	fn env() -> test_env::TestEnv {
+		let env = test_env::build(|b| b.with::<Evens>());
+		env.write_blocking(|tx| {
+			for key in (0..=8).step_by(2) { Evens::get(tx).put(&key, &(key * 10))?; }
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		env
+	}
+
+	fn keys<'a>(iter: impl Iterator<Item = Result<(&'a u8, &'a u8), Error>>) -> Vec<u8> {
+		iter.map(|get| { let (key, value) = get.unwrap(); assert_eq!(*value, key * 10); *key }).collect()
+	}
+
+	#[test]
+	fn bounds() {
+		let env = env();
+		let tx = env.read_tx().unwrap();
+		let evens = Evens::get(&tx);
+		assert_eq!(keys(evens.range(..).unwrap()), [0, 2, 4, 6, 8]);
+		assert_eq!(keys(evens.range(2..6).unwrap()), [2, 4]);
+		assert_eq!(keys(evens.range(2..=6).unwrap()), [2, 4, 6]);
+		assert_eq!(keys(evens.range(3..7).unwrap()), [4, 6]);
+		assert_eq!(keys(evens.range((Bound::Excluded(2), Bound::Included(6))).unwrap()), [4, 6]);
+		assert_eq!(keys(evens.range((Bound::Excluded(3), Bound::Excluded(8))).unwrap()), [4, 6]);
+		assert_eq!(keys(evens.range(..=0).unwrap()), [0]);
+		assert_eq!(keys(evens.range(..0).unwrap()), []);
+		assert_eq!(keys(evens.range(9..).unwrap()), []);
+		assert_eq!(keys(evens.range((Bound::Included(6), Bound::Excluded(2))).unwrap()), []);
+		assert_eq!(keys(evens.iter_from(&5).unwrap()), [6, 8]);
+		assert_eq!(keys(evens.iter_from(&6).unwrap()), [6, 8]);
+	}
+
+	#[test]
+	fn rev() {
+		let env = env();
+		let tx = env.read_tx().unwrap();
+		let evens = Evens::get(&tx);
+		assert_eq!(keys(evens.range(2..=6).unwrap().rev()), [6, 4, 2]);
+		assert_eq!(keys(evens.range(1..7).unwrap().rev()), [6, 4, 2]);
+		assert_eq!(keys(evens.range((Bound::Excluded(2), Bound::Excluded(8))).unwrap().rev()), [6, 4]);
+		assert_eq!(keys(evens.range(..9).unwrap().rev()), [8, 6, 4, 2, 0]);
+		assert_eq!(keys(evens.range(..0).unwrap().rev()), []);
+		assert_eq!(keys(evens.iter_rev().unwrap()), [8, 6, 4, 2, 0]);
+	}
+
+	#[test]
+	fn iter_rev_from() {
+		let env = env();
+		let tx = env.read_tx().unwrap();
+		let evens = Evens::get(&tx);
+		assert_eq!(keys(evens.iter_rev_from(&4).unwrap()), [4, 2, 0]);
+		assert_eq!(keys(evens.iter_rev_from(&5).unwrap()), [4, 2, 0]);
+		assert_eq!(keys(evens.iter_rev_from(&0).unwrap()), [0]);
+		assert_eq!(keys(evens.iter_rev_from(&100).unwrap()), [8, 6, 4, 2, 0]);
+	}
+
+	#[test]
+	fn both_ends_meet() {
+		let env = env();
+		let tx = env.read_tx().unwrap();
+		let evens = Evens::get(&tx);
+		let mut iter = evens.iter().unwrap();
+		let mut next = |back: bool| if back { iter.next_back() } else { iter.next() }.map(|get| *get.unwrap().0);
+		assert_eq!(next(false), Some(0));
+		assert_eq!(next(true), Some(8));
+		assert_eq!(next(false), Some(2));
+		assert_eq!(next(true), Some(6));
+		assert_eq!(next(true), Some(4));
+		assert_eq!(next(false), None);
+		assert_eq!(next(true), None);
+
+		let mut iter = evens.range(1..=4).unwrap();
+		assert_eq!(iter.next_back().map(|get| *get.unwrap().0), Some(4));
+		assert_eq!(iter.next().map(|get| *get.unwrap().0), Some(2));
+		assert!(iter.next().is_none());
+		assert!(iter.next_back().is_none());
+	}
+
+	#[test]
+	fn writes_while_iterating() {
+		let env = env();
+		env.write_blocking(|tx| {
+			let evens = Evens::get(tx);
+			let mut seen = Vec::new();
+			for get in evens.range(..).unwrap() {
+				let key = *get?.0;
+				seen.push(key);
+				// lots of writes, so pages get split and moved around under the iterator
+				for filler in 100..200 { evens.put(&filler, &0)?; evens.delete(&filler)?; }
+				if key % 2 == 0 { evens.put(&(key + 1), &((key + 1) * 10))?; }
+			}
+			assert_eq!(seen, (0..=9).collect::<Vec<_>>());
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
 	}
 }
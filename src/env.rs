@@ -1,144 +1,829 @@
 use culpa::throws;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::future::Future;
 
-use super::{lmdb::{self, DbFlags}, DbName, RoTxn, RwTxn, Transaction, error::Error, Table};
+mod batch;
+mod runtime;
+
+pub use runtime::BlockingJob;
+
+use super::{lmdb::{self, DbFlags}, DbName, RoTxn, RwTxn, Transaction, error::Error, Table, TableStats, Meta, MetaField};
 
 pub struct Env {
-	raw_env: *mut lmdb_sys::MDB_env,
-	dbs: HashMap<&'static [u8], lmdb_sys::MDB_dbi>,
-	write_sema: tokio::sync::Semaphore,
+	pub(super) raw_env: *mut lmdb_sys::MDB_env,
+	dbs: HashMap<Cow<'static, [u8]>, lmdb_sys::MDB_dbi>,
+	write_lock: runtime::WriteLock,
+	spawner: runtime::Spawner,
+	map_growth: Option<(MapGrowth, usize)>,
+	pub(super) readers: Readers,
+	read_txn_warnings: Option<ReadTxnWarnings>,
+	// reset read txns to renew instead of beginning new ones
+	read_txn_pool: std::sync::Mutex<Vec<*mut lmdb_sys::MDB_txn>>,
+	read_txn_pool_size: usize,
+	batch: Option<batch::Batch>,
+	// opened with `build_read_only`, so there's nothing to sync
+	read_only: bool,
+	in_flight: std::sync::Arc<runtime::InFlight>,
 }
 
 pub struct EnvBuilder {
 	raw_env: *mut lmdb_sys::MDB_env,
-	dbs: Vec<(&'static [u8], enumflags2::BitFlags<lmdb::DbFlags>)>,
+	dbs: Vec<(Cow<'static, [u8]>, enumflags2::BitFlags<lmdb::DbFlags>)>,
+	migrations: Option<Vec<Migration>>,
+	map_growth: Option<(MapGrowth, usize)>,
+	read_txn_warnings: Option<ReadTxnWarnings>,
+	read_txn_pool_size: usize,
+	group_commit: Option<usize>,
+	spawner: runtime::Spawner,
+	flags: u32,
+	mode: u32,
+}
+
+/// An [`Env`] opened with [`EnvBuilder::build_read_only`], which only has the read methods.
+/// It's also what `tx.env()` hands out for any env, so writes can't be reached through a txn either.
+/// ```compile_fail
+/// use batadase::Transaction;
+/// fn write(env: &batadase::ReadOnlyEnv) {
+///     let tx = env.read_tx().unwrap();
+///     let _ = tx.env().write_blocking(|_| ());
+/// }
+/// ```
+#[repr(transparent)]
+pub struct ReadOnlyEnv(Env);
+
+impl ReadOnlyEnv {
+	pub fn db(&self, name: &[u8]) -> Option<lmdb_sys::MDB_dbi> { self.0.db(name) }
+	pub fn version(&self) -> Result<u32, Error> { self.0.version() }
+	pub fn info(&self) -> Result<EnvInfo, Error> { self.0.info() }
+	pub fn stat(&self) -> Result<TableStats, Error> { self.0.stat() }
+	pub fn reader_list(&self) -> Result<(), Error> { self.0.reader_list() }
+	pub fn read_txns(&self) -> Vec<OpenReadTxn> { self.0.read_txns() }
+	pub fn readers(&self) -> Result<Vec<ReaderInfo>, Error> { self.0.readers() }
+	pub fn reader_check(&self) -> Result<usize, Error> { self.0.reader_check() }
+	pub fn spawn_reader_check(&'static self, every: std::time::Duration) -> ReaderCheck { self.0.spawn_reader_check(every) }
+	pub fn read_tx(&self) -> Result<RoTxn<'_>, Error> { self.0.read_tx() }
+	pub fn copy_to(&'static self, path: &std::ffi::CStr, compact: bool) -> impl Future<Output = Result<(), Error>> + use<> { self.0.copy_to(path, compact) }
+	pub fn copy_to_fd(&'static self, fd: lmdb_sys::mdb_filehandle_t, compact: bool) -> impl Future<Output = Result<(), Error>> + use<> { self.0.copy_to_fd(fd, compact) }
+	pub async fn backup_to_writer(&'static self, writer: impl std::io::Write + Send + 'static) -> Result<(), Error> { self.0.backup_to_writer(writer).await }
+	/// see [`SharedEnv::close`], there's nothing to sync
+	pub async fn close(self) -> Result<(), Error> { self.0.close().await }
+}
+
+/// A [`ReadOnlyEnv`] behind an `Arc`, see [`SharedEnv`]
+#[derive(Clone)]
+pub struct SharedReadOnlyEnv(SharedEnv);
+
+impl From<ReadOnlyEnv> for SharedReadOnlyEnv {
+	fn from(env: ReadOnlyEnv) -> Self { Self(env.0.into()) }
+}
+
+impl std::ops::Deref for SharedReadOnlyEnv {
+	type Target = ReadOnlyEnv;
+	fn deref(&self) -> &ReadOnlyEnv { self.0.as_read_only() }
+}
+
+// the ones that don't need a `&'static ReadOnlyEnv`
+impl SharedReadOnlyEnv {
+	pub fn spawn_reader_check(&self, every: std::time::Duration) -> ReaderCheck { self.0.spawn_reader_check(every) }
+	pub fn copy_to(&self, path: &std::ffi::CStr, compact: bool) -> impl Future<Output = Result<(), Error>> + use<> { self.0.copy_to(path, compact) }
+	pub fn copy_to_fd(&self, fd: lmdb_sys::mdb_filehandle_t, compact: bool) -> impl Future<Output = Result<(), Error>> + use<> { self.0.copy_to_fd(fd, compact) }
+	pub fn backup_to_writer(&self, writer: impl std::io::Write + Send + 'static) -> impl Future<Output = Result<(), Error>> { self.0.backup_to_writer(writer) }
+	/// see [`SharedEnv::close`], there's nothing to sync
+	pub async fn close(self) -> Result<(), Error> { self.0.close().await }
+}
+
+/// An [`Env`] behind an `Arc`, for envs that are opened and closed at runtime, e.g. in tests or one per tenant.
+/// Its async writes and copies keep a clone alive instead of needing a `&'static Env`,
+/// and the env closes when the last clone is dropped.
+#[derive(Clone)]
+pub struct SharedEnv(std::sync::Arc<Env>);
+
+impl From<Env> for SharedEnv {
+	fn from(env: Env) -> Self { Self(std::sync::Arc::new(env)) }
+}
+
+impl std::ops::Deref for SharedEnv {
+	type Target = Env;
+	fn deref(&self) -> &Env { &self.0 }
+}
+
+impl SharedEnv {
+	/// see [`Env::write`]
+	pub fn write<Res, Job>(&self, job: Job) -> impl Future<Output = Result<Res, Error>> + use<Res, Job> where
+		Res: Send + 'static,
+		Job: (FnMut(&RwTxn) -> Res) + Send + 'static,
+	{
+		Env::write_on(Env::track(self.0.clone()), job, |_| true)
+	}
+
+	/// see [`Env::try_write`]
+	pub fn try_write<Res, Err, Job>(&self, job: Job) -> impl Future<Output = Result<Result<Res, Err>, Error>> + use<Res, Err, Job> where
+		Res: Send + 'static,
+		Job: (FnMut(&RwTxn) -> Result<Res, Err>) + Send + 'static,
+		Err: Send + 'static,
+	{
+		Env::write_on(Env::track(self.0.clone()), job, Result::is_ok)
+	}
+
+	/// see [`Env::write_async`]
+	pub fn write_async<Res>(&self, job: impl for <'tx> WriteCallback<'tx, Res>) -> impl Future<Output = Result<Res, Error>> {
+		let env = Env::track(self.0.clone());
+		async move { env.write_async(job).await }
+	}
+
+	/// see [`Env::try_write_async`]
+	pub fn try_write_async<Res, Err>(&self, job: impl for <'tx> WriteCallback<'tx, Result<Res, Err>>) -> impl Future<Output = Result<Result<Res, Err>, Error>> {
+		let env = Env::track(self.0.clone());
+		async move { env.try_write_async(job).await }
+	}
+
+	pub fn spawn_reader_check(&self, every: std::time::Duration) -> ReaderCheck { Env::spawn_reader_check_on(self.0.clone(), every) }
+	pub fn copy_to(&self, path: &std::ffi::CStr, compact: bool) -> impl Future<Output = Result<(), Error>> + use<> { Env::copy_to_on(Env::track(self.0.clone()), path, compact) }
+	pub fn copy_to_fd(&self, fd: lmdb_sys::mdb_filehandle_t, compact: bool) -> impl Future<Output = Result<(), Error>> + use<> { Env::copy_to_fd_on(Env::track(self.0.clone()), fd, compact) }
+	pub fn backup_to_writer(&self, writer: impl std::io::Write + Send + 'static) -> impl Future<Output = Result<(), Error>> { Env::backup_to_writer_on(Env::track(self.0.clone()), writer) }
+
+	/// Waits for the writes and copies started from this env's clones before this, even ones that aren't polled yet,
+	/// then syncs the env to disk and closes it.
+	/// Fails with `EnvInUse` while read txns or other clones are still around,
+	/// in which case the env closes once the last clone is dropped.
+	#[throws]
+	pub async fn close(self) {
+		loop {
+			let _lock = self.write_lock.lock().await;
+			let in_flight = self.in_flight.jobs();
+			// each write or copy in flight holds a clone
+			if std::sync::Arc::strong_count(&self.0) - 1 > in_flight { culpa::throw!(Error::EnvInUse("other clones are still alive")); }
+			if in_flight > 0 {
+				// they need the lock to finish
+				drop(_lock);
+				self.in_flight.idle().await;
+				continue;
+			}
+			if self.readers.open() > 0 { culpa::throw!(Error::EnvInUse("read txns are still open")); }
+			if !self.read_only {
+				let sync_env = self.0.clone();
+				self.spawn_blocking(move || lmdb::env_sync(sync_env.raw_env)).await?;
+			}
+			break;
+		}
+		let Ok(env) = std::sync::Arc::try_unwrap(self.0) else { culpa::throw!(Error::EnvInUse("other clones are still alive")); };
+		drop(env);
+	}
+}
+
+// a HANDLE on Windows is a pointer, which isn't Send
+struct FileHandle(lmdb_sys::mdb_filehandle_t);
+unsafe impl Send for FileHandle {}
+
+#[cfg(unix)]
+fn raw_handle(file: &impl std::os::fd::AsRawFd) -> lmdb_sys::mdb_filehandle_t { file.as_raw_fd() }
+#[cfg(windows)]
+fn raw_handle(file: &impl std::os::windows::io::AsRawHandle) -> lmdb_sys::mdb_filehandle_t { file.as_raw_handle() }
+
+/// see [`Env::info`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnvInfo {
+	pub map_size: usize,
+	/// the map is used up to this page
+	pub last_page: usize,
+	pub last_txn_id: usize,
+	pub max_readers: u32,
+	/// reader slots in use, including ones left by dead processes
+	pub readers_in_use: u32,
+}
+
+impl From<lmdb_sys::MDB_envinfo> for EnvInfo {
+	fn from(info: lmdb_sys::MDB_envinfo) -> Self {
+		Self {
+			map_size: info.me_mapsize,
+			last_page: info.me_last_pgno,
+			last_txn_id: info.me_last_txnid,
+			max_readers: info.me_maxreaders,
+			readers_in_use: info.me_numreaders,
+		}
+	}
+}
+
+/// a slot in the reader lock table, see [`Env::readers`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReaderInfo {
+	pub pid: i32,
+	pub thread: usize,
+	/// None if the slot isn't in a txn right now
+	pub txn_id: Option<usize>,
+}
+
+/// stops the background reader check when dropped, see [`Env::spawn_reader_check`]
+#[must_use = "the reader check stops when this is dropped"]
+pub struct ReaderCheck {
+	stop: Option<std::sync::mpsc::Sender<()>>,
+	thread: Option<std::thread::JoinHandle<()>>,
+}
+
+// waits for the thread so its handle on the env is gone too, see `SharedEnv::close`
+impl Drop for ReaderCheck {
+	fn drop(&mut self) {
+		drop(self.stop.take());
+		if let Some(thread) = self.thread.take() { let _ = thread.join(); }
+	}
+}
+
+/// How much to grow the map by when a write hits `MapFull`, see [`EnvBuilder::map_growth`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapGrowth {
+	/// add this many bytes
+	Add(usize),
+	/// multiply the size by this
+	Multiply(usize),
+}
+
+/// When to warn about read txns, which keep the pages they see from being reused and so bloat the db,
+/// see [`EnvBuilder::read_txn_warnings`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadTxnWarnings {
+	/// warn when a `RoTxn` open for longer than this is dropped
+	pub max_age: std::time::Duration,
+	/// warn when a write commits while the oldest `RoTxn` is this many txns behind
+	pub max_lag: usize,
+}
+
+impl Default for ReadTxnWarnings {
+	fn default() -> Self {
+		Self { max_age: std::time::Duration::from_secs(10), max_lag: 1000 }
+	}
+}
+
+/// an open `RoTxn` of this process, see [`Env::read_txns`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenReadTxn {
+	/// the txn the `RoTxn` sees the db as of
+	pub txn_id: usize,
+	pub age: std::time::Duration,
+}
+
+// how long a resize waits for this process' read txns to close before giving up
+const READERS_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// counts this process' open read txns, since the map can only be resized when there are none,
+// and keeps track of `RoTxn`s for `Env::read_txns` and the read txn warnings
+#[derive(Default)]
+pub(super) struct Readers {
+	state: std::sync::Mutex<ReadersState>,
+	changed: std::sync::Condvar,
+}
+
+#[derive(Default)]
+struct ReadersState {
+	open: usize,
+	resizing: bool,
+	next_id: u64,
+	// by RoTxn id, txn id and start
+	txns: HashMap<u64, (usize, std::time::Instant)>,
+	// the RoTxn last warned about lagging, so it's warned about only once
+	lag_warned: Option<u64>,
+}
+
+impl Readers {
+	// blocks while the map is being resized
+	fn enter(&self) {
+		let mut state = self.changed.wait_while(self.state.lock().unwrap(), |state| state.resizing).unwrap();
+		state.open += 1;
+	}
+
+	pub(super) fn leave(&self) {
+		let mut state = self.state.lock().unwrap();
+		state.open -= 1;
+		// only `drain` waits for readers to leave
+		if state.resizing && state.open == 0 { self.changed.notify_all(); }
+	}
+
+	// returns the id for `unregister`
+	fn register(&self, txn_id: usize, started: std::time::Instant) -> u64 {
+		let mut state = self.state.lock().unwrap();
+		let id = state.next_id;
+		state.next_id += 1;
+		state.txns.insert(id, (txn_id, started));
+		id
+	}
+
+	fn unregister(&self, id: u64) {
+		self.state.lock().unwrap().txns.remove(&id);
+	}
+
+	// read txns and copies in progress
+	fn open(&self) -> usize {
+		self.state.lock().unwrap().open
+	}
+
+	// oldest first
+	fn txns(&self) -> Vec<(u64, usize, std::time::Instant)> {
+		let mut txns = self.state.lock().unwrap().txns.iter().map(|(&id, &(txn_id, started))| (id, txn_id, started)).collect::<Vec<_>>();
+		txns.sort_by_key(|&(_, txn_id, started)| (txn_id, started));
+		txns
+	}
+
+	// stops new read txns and waits for open ones to close, returns false if they don't in time
+	fn drain(&self) -> bool {
+		let mut state = self.changed.wait_while(self.state.lock().unwrap(), |state| state.resizing).unwrap();
+		state.resizing = true;
+		let (state, timeout) = self.changed.wait_timeout_while(state, READERS_DRAIN_TIMEOUT, |state| state.open > 0).unwrap();
+		if timeout.timed_out() {
+			drop(state);
+			self.resume();
+			return false;
+		}
+		true
+	}
+
+	fn resume(&self) {
+		self.state.lock().unwrap().resizing = false;
+		self.changed.notify_all();
+	}
 }
 
+/// A schema migration, see [`EnvBuilder::migrations`]
+pub type Migration = Box<dyn FnOnce(&RwTxn) -> Result<(), Error> + Send>;
+
 unsafe impl Send for Env {}
 unsafe impl Sync for Env {}
 unsafe impl Send for EnvBuilder {}
 unsafe impl Sync for EnvBuilder {}
 
-pub trait WriteCallback<'tx, T>: FnOnce(&'tx RwTxn<'static>) -> Self::Fut {
+// txns borrow the env, so the only ones left here are pooled ones
+impl Drop for Env {
+	fn drop(&mut self) {
+		for raw in self.read_txn_pool.get_mut().unwrap_or_else(std::sync::PoisonError::into_inner).drain(..) {
+			unsafe { lmdb_sys::mdb_txn_abort(raw); }
+		}
+		for &dbi in self.dbs.values() { lmdb::dbi_close(self.raw_env, dbi); }
+		lmdb::env_close(self.raw_env);
+	}
+}
+
+// a builder that's dropped instead of built still has to close its handle
+impl Drop for EnvBuilder {
+	fn drop(&mut self) {
+		if !self.raw_env.is_null() { lmdb::env_close(self.raw_env); }
+	}
+}
+
+pub trait WriteCallback<'tx, T>: FnOnce(&'tx RwTxn<'tx>) -> Self::Fut {
     type Fut: Future<Output = T>;
 }
 
 impl<'tx, T, Out, F> WriteCallback<'tx, T> for F where
 	Out: Future<Output = T>,
-	F: FnOnce(&'tx RwTxn<'static>) -> Out,
+	F: FnOnce(&'tx RwTxn<'tx>) -> Out,
 {
     type Fut = Out;
 }
 
+// what's kept alive while a job runs on the blocking executor, `&'static Env` or `SharedEnv`'s `Arc<Env>`
+trait Handle: std::ops::Deref<Target = Env> + Clone + Send + Sync + 'static {}
+impl<H: std::ops::Deref<Target = Env> + Clone + Send + Sync + 'static> Handle for H {}
+
 fn complain_about_lock_hold(instant: std::time::Instant) {
 	let lock_held = instant.elapsed().as_secs_f32();
 	match lock_held {
-		10.0.. => log::error!("async write lock held for {lock_held:.2} secs"),
-		2.5.. => log::warn!("async write lock held for {lock_held:.2} secs"),
-		0.25.. => log::info!("async write lock held for {lock_held:.2} secs"),
-		_ => log::trace!("async write lock held for {lock_held:.2} secs"),
+		10.0.. => log::error!("write lock held for {lock_held:.2} secs"),
+		2.5.. => log::warn!("write lock held for {lock_held:.2} secs"),
+		0.25.. => log::info!("write lock held for {lock_held:.2} secs"),
+		_ => log::trace!("write lock held for {lock_held:.2} secs"),
 	}
 }
 
 impl Env {
+	// `ReadOnlyEnv` is a transparent wrapper, so this is just a cast
+	pub(super) fn as_read_only(&self) -> &ReadOnlyEnv {
+		unsafe { &*std::ptr::from_ref(self).cast::<ReadOnlyEnv>() }
+	}
+
 	#[throws]
 	pub fn builder() -> EnvBuilder {
-		EnvBuilder { raw_env: lmdb::env_create()?, dbs: Vec::new() }
+		EnvBuilder {
+			raw_env: lmdb::env_create()?,
+			dbs: Vec::new(),
+			migrations: None,
+			map_growth: None,
+			read_txn_warnings: Some(ReadTxnWarnings::default()),
+			read_txn_pool_size: 0,
+			group_commit: None,
+			spawner: runtime::default_spawner(),
+			flags:
+				lmdb_sys::MDB_NOMETASYNC | // maybe lose last transaction in case of a crash
+				lmdb_sys::MDB_NOTLS |      // don't use thread-local storage - read and write transactions can be on any thread, still at most 1 write tx
+				lmdb_sys::MDB_NORDAHEAD,   // don't readahead - useful when datasets are bigger than ram (does nothing on Windows)
+			// permissions for db files on Unix - read/write/not execute
+			mode: 0o664,
+		}
 	}
 
-	pub fn db(&self, name: &'static [u8]) -> Option<lmdb_sys::MDB_dbi> {
+	pub fn db(&self, name: &[u8]) -> Option<lmdb_sys::MDB_dbi> {
 		self.dbs.get(name).copied()
 	}
 
+	/// stored schema version, absent means 0
+	/// also 0 if the env was built without [`EnvBuilder::migrations`], which registers the `Meta` table
+	#[throws]
+	pub fn version(&self) -> u32 {
+		if self.db(Meta::NAME).is_none() { return 0; }
+		let tx = self.read_tx()?;
+		Meta::get(&tx).get::<u32>(&MetaField::Version)?.map_or(0, |x| x.to_native())
+	}
+
+	#[throws]
+	fn migrate(&self, migrations: Vec<Migration>) {
+		let known = migrations.len() as u32;
+		let db = self.version()?;
+		if db > known { culpa::throw!(Error::UnknownVersion { db, known }); }
+
+		for (version, migration) in (db..).zip(migrations.into_iter().skip(db as usize)) {
+			log::info!("migrating db from version {version} to {}", version + 1);
+			let tx = self.write_tx()?;
+			migration(&tx)?;
+			Meta::get(&tx).put(&MetaField::Version, &(version + 1))?;
+			tx.commit()?;
+		}
+	}
+
+	#[throws]
+	pub fn info(&self) -> EnvInfo {
+		lmdb::env_info(self.raw_env)?.into()
+	}
+
+	/// stats of the main db, which only holds the names of the tables, use [`Table::stats`] for those
+	#[throws]
+	pub fn stat(&self) -> TableStats {
+		lmdb::env_stat(self.raw_env)?.into()
+	}
+
+	#[throws]
 	pub fn reader_list(&self) {
-		unsafe extern "C" fn msg(msg: *const libc::c_char, _: *mut libc::c_void) -> i32 {
-			let cstr = std::ffi::CStr::from_ptr(msg);
-			println!("{}", cstr.to_string_lossy());
-			0
+		lmdb::reader_list(self.raw_env, |line| println!("{line}"))?;
+	}
+
+	/// this process' open `RoTxn`s, oldest first, always empty with [`EnvBuilder::read_txn_warnings`] off
+	pub fn read_txns(&self) -> Vec<OpenReadTxn> {
+		self.readers.txns().into_iter().map(|(_, txn_id, started)| OpenReadTxn { txn_id, age: started.elapsed() }).collect()
+	}
+
+	// called by RoTxn once it's closed
+	pub(super) fn read_txn_closed(&self, id: Option<u64>, started: std::time::Instant) {
+		if let Some(id) = id { self.readers.unregister(id); }
+		self.readers.leave();
+		let Some(warnings) = self.read_txn_warnings else { return; };
+		let age = started.elapsed();
+		if age > warnings.max_age {
+			log::warn!("read txn was open for {:.2} secs", age.as_secs_f32());
 		}
-		unsafe { lmdb_sys::mdb_reader_list(self.raw_env, Some(msg), std::ptr::null_mut()) };
 	}
 
+	// old read txns keep the pages they see from being reused
+	pub(super) fn complain_about_old_readers(&self) {
+		let Some(warnings) = self.read_txn_warnings else { return; };
+		let Some((id, txn_id, started)) = self.readers.txns().into_iter().next() else { return; };
+		let Ok(info) = lmdb::env_info(self.raw_env) else { return; };
+		let lag = info.me_last_txnid.saturating_sub(txn_id);
+		if lag <= warnings.max_lag { return; }
+		let mut state = self.readers.state.lock().unwrap();
+		if state.lag_warned == Some(id) { return; }
+		state.lag_warned = Some(id);
+		log::warn!("oldest read txn is {lag} txns behind and has been open for {:.2} secs", started.elapsed().as_secs_f32());
+	}
+
+	/// the reader lock table, including slots left by dead processes, see [`Env::reader_check`]
+	#[throws]
+	pub fn readers(&self) -> Vec<ReaderInfo> {
+		let mut out = String::new();
+		lmdb::reader_list(self.raw_env, |line| out.push_str(line))?;
+		// a "pid thread txnid" header, then e.g. "     12345 7f0a2c1fe640 42", with "-" for slots without a txn
+		out.lines().filter_map(|line| {
+			let mut fields = line.split_whitespace();
+			let pid = fields.next()?.parse().ok()?;
+			let thread = usize::from_str_radix(fields.next()?, 16).ok()?;
+			let txn_id = fields.next()?.parse().ok();
+			Some(ReaderInfo { pid, thread, txn_id })
+		}).collect()
+	}
+
+	/// clears reader slots left by dead processes, which otherwise count towards `ReadersFull`,
+	/// returns how many were cleared
+	#[throws]
+	pub fn reader_check(&self) -> usize {
+		lmdb::reader_check(self.raw_env)?
+	}
+
+	/// Syncs the env to disk and closes it, see [`SharedEnv::close`].
+	/// Just dropping the env closes it too, but without syncing first,
+	/// so with the default `no_meta_sync` the last commits might not survive a crash of the OS.
+	pub async fn close(self) -> Result<(), Error> { SharedEnv::from(self).close().await }
+
+	/// runs [`Env::reader_check`] on a background thread every `every` until the returned handle is dropped
+	pub fn spawn_reader_check(&'static self, every: std::time::Duration) -> ReaderCheck { Self::spawn_reader_check_on(self, every) }
+
+	fn spawn_reader_check_on(env: impl Handle, every: std::time::Duration) -> ReaderCheck {
+		let (stop, stopped) = std::sync::mpsc::channel::<()>();
+		let thread = std::thread::spawn(move || {
+			while let Err(std::sync::mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(every) {
+				match env.reader_check() {
+					Ok(0) => {},
+					Ok(cleared) => log::warn!("cleared {cleared} reader slots left by dead processes"),
+					Err(e) => log::error!("reader check failed: {e}"),
+				}
+			}
+		});
+		ReaderCheck { stop: Some(stop), thread: Some(thread) }
+	}
+
+	/// Copies the env while reads and writes go on, e.g. for backups.
+	/// `path` is a directory that has to exist and be empty, or the file itself with [`EnvBuilder::no_subdir`].
+	/// `compact` leaves out free pages, which is slower but the copy can be a lot smaller.
+	pub fn copy_to(&'static self, path: &std::ffi::CStr, compact: bool) -> impl Future<Output = Result<(), Error>> + use<> { Self::copy_to_on(self, path, compact) }
+
+	/// like [`Env::copy_to`], but writes into an open file or pipe, which has to stay open until this resolves
+	pub fn copy_to_fd(&'static self, fd: lmdb_sys::mdb_filehandle_t, compact: bool) -> impl Future<Output = Result<(), Error>> + use<> { Self::copy_to_fd_on(self, fd, compact) }
+
+	/// Streams a compacted copy of the env into `writer`, e.g. into a compressor or an upload, without a temporary file.
+	/// The copy is written into a pipe on one blocking job and forwarded to `writer` on another,
+	/// so the two have to run at the same time, see [`EnvBuilder::spawn_blocking`].
+	pub async fn backup_to_writer(&'static self, writer: impl std::io::Write + Send + 'static) -> Result<(), Error> { Self::backup_to_writer_on(self, writer).await }
+
+	fn copy_to_on<H: Handle>(env: H, path: &std::ffi::CStr, compact: bool) -> impl Future<Output = Result<(), Error>> + use<H> {
+		let path = path.to_owned();
+		Self::copy_on(env, move |env, flags| lmdb::env_copy(env.raw_env, &path, flags), compact)
+	}
+
+	fn copy_to_fd_on<H: Handle>(env: H, fd: lmdb_sys::mdb_filehandle_t, compact: bool) -> impl Future<Output = Result<(), Error>> + use<H> {
+		let fd = FileHandle(fd);
+		Self::copy_on(env, move |env, flags| { let fd = fd; lmdb::env_copy_fd(env.raw_env, fd.0, flags) }, compact)
+	}
+
+	#[throws]
+	async fn backup_to_writer_on(env: impl Handle, mut writer: impl std::io::Write + Send + 'static) {
+		let (mut pipe_reader, pipe_writer) = std::io::pipe()?;
+		let forward = env.spawn_blocking(move || {
+			std::io::copy(&mut pipe_reader, &mut writer)?;
+			writer.flush()
+		});
+		let copied = Self::copy_on(env, move |env, flags| {
+			let res = lmdb::env_copy_fd(env.raw_env, raw_handle(&pipe_writer), flags);
+			// closing the write end lets the forwarding thread see the end of the copy
+			drop(pipe_writer);
+			res
+		}, true).await;
+		// a failed writer makes the copy fail with a broken pipe, so its error comes first
+		forward.await?;
+		copied?;
+	}
+
+	// copies run in their own read tx, so they count as a reader
+	#[throws]
+	async fn copy_on<H: Handle>(env: H, copy: impl FnOnce(&Env, u32) -> Result<(), lmdb::Error> + Send + 'static, compact: bool) {
+		let flags = if compact { lmdb_sys::MDB_CP_COMPACT } else { 0 };
+		let copy_env = env.clone();
+		env.spawn_blocking(move || {
+			copy_env.readers.enter();
+			let res = copy(&copy_env, flags);
+			copy_env.readers.leave();
+			res
+		}).await?;
+	}
+
+	/// if another process grew the map, its new size is adopted once this process has no other txns open
+	/// renews a txn from the pool if there's one, see [`EnvBuilder::read_txn_pool`]
+	/// adopting a new map size blocks the thread for up to 5s waiting for the write lock, then up to 5s more
+	/// for this process' other read txns to close, which stalls a tokio worker just the same,
+	/// so it's best called from a blocking task in async code if other processes write to the db.
+	/// A thread that already holds a `RoTxn` waits on itself, so it always fails with `MapResized` after the timeout.
+	#[throws]
+	pub fn read_tx(&self) -> RoTxn<'_> {
+		let pooled = self.read_txn_pool.lock().unwrap().pop();
+		if let Some(raw) = pooled { return self.renew_read_tx(raw)?; }
+		loop {
+			self.readers.enter();
+			match lmdb::txn_begin(self.raw_env, std::ptr::null_mut(), lmdb_sys::MDB_RDONLY) {
+				Ok(raw) => return self.opened_read_tx(raw),
+				Err(lmdb::Error::MapResized) => {
+					self.readers.leave();
+					self.adopt_mapsize()?;
+				},
+				Err(e) => {
+					self.readers.leave();
+					culpa::throw!(e);
+				},
+			}
+		}
+	}
+
+	// takes ownership of the reset tx, which is aborted if renewing it fails
+	#[throws]
+	pub(super) fn renew_read_tx(&self, raw: *mut lmdb_sys::MDB_txn) -> RoTxn<'_> {
+		loop {
+			self.readers.enter();
+			let err = match lmdb::txn_renew(raw) {
+				Ok(()) => return self.opened_read_tx(raw),
+				Err(lmdb::Error::MapResized) => {
+					self.readers.leave();
+					match self.adopt_mapsize() {
+						Ok(()) => continue,
+						Err(e) => e,
+					}
+				},
+				Err(e) => {
+					self.readers.leave();
+					e.into()
+				},
+			};
+			unsafe { lmdb_sys::mdb_txn_abort(raw); }
+			culpa::throw!(err);
+		}
+	}
+
+	fn opened_read_tx(&self, raw: *mut lmdb_sys::MDB_txn) -> RoTxn<'_> {
+		let started = std::time::Instant::now();
+		// tracking them costs a couple more locks per txn, which is only worth it for the warnings
+		let id = self.read_txn_warnings.map(|_| self.readers.register(lmdb::txn_id(raw), started));
+		RoTxn { raw, env: self, id, started }
+	}
+
+	// RoTxn's Drop, keeps the tx for `read_tx` if there's room in the pool
+	pub(super) fn recycle_read_tx(&self, raw: *mut lmdb_sys::MDB_txn) {
+		let mut pool = self.read_txn_pool.lock().unwrap();
+		if pool.len() < self.read_txn_pool_size {
+			lmdb::txn_reset(raw);
+			pool.push(raw);
+		} else {
+			drop(pool);
+			unsafe { lmdb_sys::mdb_txn_abort(raw); }
+		}
+	}
+
+	// another process grew the map past our size, which can only be adopted with no txns open in this process,
+	// so this waits for the write lock and this process' read txns like `resize_map`
+	#[throws]
+	fn adopt_mapsize(&self) {
+		let Some(_lock) = self.write_lock.blocking_lock_timeout(READERS_DRAIN_TIMEOUT) else {
+			log::error!("map was resized by another process but can't be adopted, the write lock is still held after {READERS_DRAIN_TIMEOUT:?}");
+			culpa::throw!(lmdb::Error::MapResized);
+		};
+		if !self.readers.drain() {
+			log::error!("map was resized by another process but can't be adopted, read txns are still open after {READERS_DRAIN_TIMEOUT:?}");
+			culpa::throw!(lmdb::Error::MapResized);
+		}
+		// 0 means whatever size the other process set
+		let res = lmdb::env_set_mapsize(self.raw_env, 0);
+		self.readers.resume();
+		res?;
+		log::info!("adopted map size set by another process");
+	}
 	// ????? rustc lint engine?
 	#[expect(unused_braces)]
-	#[throws] pub fn read_tx(&self) -> RoTxn { RoTxn { raw: lmdb::txn_begin(self.raw_env, lmdb_sys::MDB_RDONLY)?, env: self } }
-	#[expect(unused_braces)]
-	#[throws] pub(super) fn write_tx(&self) -> RwTxn { RwTxn { raw: lmdb::txn_begin(self.raw_env, 0)?, env: self } }
+	#[throws(lmdb::Error)] pub(super) fn write_tx(&self) -> RwTxn<'_> { RwTxn { raw: lmdb::txn_begin(self.raw_env, std::ptr::null_mut(), 0)?, env: self, map_full: Default::default() } }
 
-	#[throws]
-	pub async fn write<Res, Job>(&'static self, job: Job) -> Res where
-		Res: Send + 'static,
-		Job: (FnOnce(&RwTxn) -> Res) + Send + 'static,
-	{
-		let _lock = self.write_sema.acquire().await.unwrap();
-		let now = std::time::Instant::now();
+	// the size to grow the map to if a write hits MapFull, None if it can't grow
+	#[throws(lmdb::Error)]
+	pub(super) fn grown_mapsize(&self) -> Option<usize> {
+		let Some((growth, max_mapsize)) = self.map_growth else { return None; };
+		let mapsize = lmdb::env_info(self.raw_env)?.me_mapsize;
+		let page_size = lmdb::env_stat(self.raw_env)?.ms_psize as usize;
+		let grown = match growth {
+			MapGrowth::Add(bytes) => mapsize.saturating_add(bytes),
+			MapGrowth::Multiply(factor) => mapsize.saturating_mul(factor),
+		};
+		let grown = grown.min(max_mapsize) / page_size * page_size;
+		if grown <= mapsize {
+			log::warn!("map is full and already at its max size of {mapsize} bytes");
+			return None;
+		}
+		Some(grown)
+	}
+
+	// must be called with the write lock held and no write tx open
+	#[throws(lmdb::Error)]
+	pub(super) fn resize_map(&self, mapsize: usize) {
+		if !self.readers.drain() {
+			log::error!("map is full but can't be resized, read txns are still open after {READERS_DRAIN_TIMEOUT:?}");
+			culpa::throw!(lmdb::Error::MapFull);
+		}
+		let res = lmdb::env_set_mapsize(self.raw_env, mapsize);
+		self.readers.resume();
+		res?;
+		log::info!("map resized to {mapsize} bytes");
+	}
 
-		let res = tokio::task::spawn_blocking(move || {
+	// commits the tx if `keep(&res)`,
+	// re-runs the job with a bigger map if it hits MapFull and `EnvBuilder::map_growth` allows it
+	#[throws]
+	fn run_write<Res>(&self, mut job: impl FnMut(&RwTxn) -> Res, keep: impl Fn(&Res) -> bool) -> Res {
+		loop {
 			let tx = self.write_tx()?;
 			let res = job(&tx);
-			tx.commit()?;
-			Result::<_, crate::Error>::Ok(res)
-		}).await.expect("tokio spawn_blocking failed");
-		drop(_lock);
-		complain_about_lock_hold(now);
-		res?
+			if tx.hit_map_full() {
+				if let Some(mapsize) = self.grown_mapsize()? {
+					tx.abort();
+					self.resize_map(mapsize)?;
+					continue;
+				}
+			}
+			if !keep(&res) {
+				tx.abort();
+				return res;
+			}
+			match tx.commit() {
+				Ok(()) => return res,
+				Err(Error::Lmdb(lmdb::Error::MapFull)) => match self.grown_mapsize()? {
+					Some(mapsize) => self.resize_map(mapsize)?,
+					None => culpa::throw!(lmdb::Error::MapFull),
+				},
+				Err(e) => culpa::throw!(e),
+			}
+		}
+	}
+
+	// async jobs can't be re-run, but the map can still be grown for the next write
+	#[throws]
+	fn grow_after_map_full<'env>(&self, tx: RwTxn<'env>) -> RwTxn<'env> {
+		if !tx.hit_map_full() { return tx; }
+		let Some(mapsize) = self.grown_mapsize()? else { return tx; };
+		tx.abort();
+		self.resize_map(mapsize)?;
+		culpa::throw!(lmdb::Error::MapFull);
+	}
+
+	/// the job is re-run if it hits `MapFull` and the map can grow, see [`EnvBuilder::map_growth`]
+	/// with [`EnvBuilder::group_commit`] it might run in the same tx as other jobs
+	pub async fn write<Res, Job>(&'static self, job: Job) -> Result<Res, Error> where
+		Res: Send + 'static,
+		Job: (FnMut(&RwTxn) -> Res) + Send + 'static,
+	{
+		Self::write_on(self, job, |_| true).await
 	}
 
 	/// outer result is whether DB ops failed or not,
 	/// inner result is whether the job failed or not
 	/// the tx isn't committed if the job fails
-	#[throws]
-	pub async fn try_write<Res, Err, Job>(&'static self, job: Job) -> Result<Res, Err> where
+	/// the job is re-run if it hits `MapFull` and the map can grow, see [`EnvBuilder::map_growth`]
+	/// with [`EnvBuilder::group_commit`] it might run in the same tx as other jobs, in a nested tx that's aborted if it fails
+	pub async fn try_write<Res, Err, Job>(&'static self, job: Job) -> Result<Result<Res, Err>, Error> where
 		Res: Send + 'static,
-		Job: (FnOnce(&RwTxn) -> Result<Res, Err>) + Send + 'static,
+		Job: (FnMut(&RwTxn) -> Result<Res, Err>) + Send + 'static,
 		Err: Send + 'static,
 	{
-		let _lock = self.write_sema.acquire().await.unwrap();
+		Self::write_on(self, job, Result::is_ok).await
+	}
+
+	// commits the job's tx if `keep(&res)`
+	#[throws]
+	async fn write_on<Res, Job>(env: impl Handle, job: Job, keep: fn(&Res) -> bool) -> Res where
+		Res: Send + 'static,
+		Job: (FnMut(&RwTxn) -> Res) + Send + 'static,
+	{
+		if env.batch.is_some() { return Self::write_batched(env, job, keep).await?; }
+
+		let _lock = env.write_lock.lock().await;
 		let now = std::time::Instant::now();
 
-		let res = tokio::task::spawn_blocking(move || {
-			let tx = self.write_tx()?;
-			let res = job(&tx);
-			if res.is_ok() {
-				tx.commit()?;
-			} else {
-				tx.abort();
-			}
-			Result::<_, crate::Error>::Ok(res)
-		}).await.expect("tokio spawn_blocking failed");
+		let job_env = env.clone();
+		let res = env.spawn_blocking(move || job_env.run_write(job, keep)).await;
 		drop(_lock);
 		complain_about_lock_hold(now);
+		env.complain_about_old_readers();
 		res?
 	}
 
+	/// if the job hits `MapFull` and the map can grow, it's grown but the job isn't re-run and `MapFull` is returned
 	#[throws]
-	pub async fn write_async<Res>(&'static self, job: impl for <'tx> WriteCallback<'tx, Res>) -> Res {
-		let _lock = self.write_sema.acquire().await.unwrap();
+	pub async fn write_async<Res>(&self, job: impl for <'tx> WriteCallback<'tx, Res>) -> Res {
+		let _lock = self.write_lock.lock().await;
 		let now = std::time::Instant::now();
 
 		let res = {
 			let tx = self.write_tx()?;
 			let res = job(&tx).await;
+			let tx = self.grow_after_map_full(tx)?;
 			tx.commit()?;
 			res
 		};
 		drop(_lock);
 		complain_about_lock_hold(now);
+		self.complain_about_old_readers();
 		res
 	}
 
 	/// outer result is whether DB ops failed or not,
 	/// inner result is whether the job failed or not
 	/// the tx isn't committed if the job fails
+	/// if the job hits `MapFull` and the map can grow, it's grown but the job isn't re-run and `MapFull` is returned
 	#[throws]
-	pub async fn try_write_async<Res, Err>(&'static self, job: impl for <'tx> WriteCallback<'tx, Result<Res, Err>>) -> Result<Res, Err> {
-		let _lock = self.write_sema.acquire().await.unwrap();
+	pub async fn try_write_async<Res, Err>(&self, job: impl for <'tx> WriteCallback<'tx, Result<Res, Err>>) -> Result<Res, Err> {
+		let _lock = self.write_lock.lock().await;
 		let now = std::time::Instant::now();
 
 		let res = {
-			let tx: RwTxn<'static> = self.write_tx()?;
+			let tx = self.write_tx()?;
 			let res = job(&tx).await;
+			let tx = self.grow_after_map_full(tx)?;
 			if res.is_ok() {
 				tx.commit()?;
 			} else {
@@ -148,39 +833,41 @@ impl Env {
 		};
 		drop(_lock);
 		complain_about_lock_hold(now);
+		self.complain_about_old_readers();
 		res
 	}
 
-	// unclear if useful?
-	/*
+	/// for sync code like CLI tools, waits for the same write lock as `write` without needing an async runtime
+	/// blocks the thread, so use `write` in async code
+	/// never batched with other jobs, even with [`EnvBuilder::group_commit`]
+	/// the job is re-run if it hits `MapFull` and the map can grow, see [`EnvBuilder::map_growth`]
 	#[throws]
-	pub fn blocking_write<Res, F: (FnOnce(&RwTxn) -> Res)>(job: F) -> Res {
-		let _lock = tokio::task::block_in_place(|| ENV.write_lock.lock());
+	pub fn write_blocking<Res>(&self, job: impl FnMut(&RwTxn) -> Res) -> Res {
+		let _lock = self.write_lock.blocking_lock();
 		let now = std::time::Instant::now();
-		let tx = ENV.write_tx()?;
-		let res = job(&tx);
-		tx.commit()?;
+
+		let res = self.run_write(job, |_| true);
 		drop(_lock);
-		log::debug!("write lock held for {:.2} secs", now.elapsed().as_secs_f32());
-		res
+		complain_about_lock_hold(now);
+		self.complain_about_old_readers();
+		res?
 	}
 
+	/// outer result is whether DB ops failed or not,
+	/// inner result is whether the job failed or not
+	/// the tx isn't committed if the job fails
+	/// otherwise the same as `write_blocking`
 	#[throws]
-	pub fn blocking_try_write<Res, F: (FnOnce(&RwTxn) -> anyhow::Result<Res>)>(job: F) -> anyhow::Result<Res> {
-		let _lock = tokio::task::block_in_place(|| ENV.write_lock.lock());
+	pub fn try_write_blocking<Res, Err>(&self, job: impl FnMut(&RwTxn) -> Result<Res, Err>) -> Result<Res, Err> {
+		let _lock = self.write_lock.blocking_lock();
 		let now = std::time::Instant::now();
-		let tx = ENV.write_tx()?;
-		let res = job(&tx);
-		if res.is_ok() {
-			tx.commit()?;
-		} else {
-			tx.abort();
-		}
+
+		let res = self.run_write(job, Result::is_ok);
 		drop(_lock);
-		log::debug!("write lock held for {:.2} secs", now.elapsed().as_secs_f32());
-		res
+		complain_about_lock_hold(now);
+		self.complain_about_old_readers();
+		res?
 	}
-	*/
 }
 
 impl EnvBuilder {
@@ -196,34 +883,398 @@ impl EnvBuilder {
 		self
 	}
 
+	/// don't fsync after commit, a crash may lose the last transactions or corrupt the db, unless the OS is fine
+	#[must_use]
+	pub fn no_sync(self, yes: bool) -> Self { self.flag(lmdb_sys::MDB_NOSYNC, yes) }
+
+	/// don't fsync the meta page after commit, a crash may lose the last transaction, on by default
+	/// turn it off for a full fsync on every commit
+	#[must_use]
+	pub fn no_meta_sync(self, yes: bool) -> Self { self.flag(lmdb_sys::MDB_NOMETASYNC, yes) }
+
+	/// use a writeable memory map, faster but stray writes through pointers can corrupt the db
+	#[must_use]
+	pub fn write_map(self, yes: bool) -> Self { self.flag(lmdb_sys::MDB_WRITEMAP, yes) }
+
+	/// flush the writeable memory map asynchronously, requires [`EnvBuilder::write_map`]
+	#[must_use]
+	pub fn map_async(self, yes: bool) -> Self { self.flag(lmdb_sys::MDB_MAPASYNC, yes) }
+
+	/// the path is the db file itself instead of a directory containing it
+	#[must_use]
+	pub fn no_subdir(self, yes: bool) -> Self { self.flag(lmdb_sys::MDB_NOSUBDIR, yes) }
+
+	/// don't use locks, the caller has to make sure there's only one writer and no readers of stale pages
+	#[must_use]
+	pub fn no_lock(self, yes: bool) -> Self { self.flag(lmdb_sys::MDB_NOLOCK, yes) }
+
+	/// let the OS read ahead, off by default since it hurts when datasets are bigger than ram
+	#[must_use]
+	pub fn readahead(self, yes: bool) -> Self { self.flag(lmdb_sys::MDB_NORDAHEAD, !yes) }
+
+	/// permissions for the db files on Unix, 0o664 by default, ignored on Windows
+	#[must_use]
+	pub fn mode(mut self, mode: u32) -> Self {
+		self.mode = mode;
+		self
+	}
+
+	fn flag(mut self, flag: u32, on: bool) -> Self {
+		if on { self.flags |= flag; } else { self.flags &= !flag; }
+		self
+	}
+
+	#[throws]
+	fn validate(&self) {
+		let has = |flag| self.flags & flag != 0;
+		if has(lmdb_sys::MDB_MAPASYNC) && !has(lmdb_sys::MDB_WRITEMAP) { culpa::throw!(Error::InvalidConfig("map_async requires write_map")); }
+		if has(lmdb_sys::MDB_RDONLY) {
+			if has(lmdb_sys::MDB_WRITEMAP) { culpa::throw!(Error::InvalidConfig("build_read_only can't be combined with write_map")); }
+			if self.map_growth.is_some() { culpa::throw!(Error::InvalidConfig("build_read_only can't be combined with map_growth")); }
+			if self.migrations.is_some() { culpa::throw!(Error::InvalidConfig("build_read_only can't be combined with migrations")); }
+		}
+		// nested txns don't work with a writeable map
+		if self.group_commit.is_some() && has(lmdb_sys::MDB_WRITEMAP) { culpa::throw!(Error::InvalidConfig("group_commit can't be combined with write_map")); }
+		if self.group_commit == Some(0) { culpa::throw!(Error::InvalidConfig("group_commit needs room for at least 1 job")); }
+		match self.map_growth {
+			Some((MapGrowth::Add(0), _)) => culpa::throw!(Error::InvalidConfig("map_growth can't add 0 bytes")),
+			Some((MapGrowth::Multiply(0 | 1), _)) => culpa::throw!(Error::InvalidConfig("map_growth has to multiply by at least 2")),
+			_ => {},
+		}
+	}
+
+	/// when to warn about long-lived read txns, `ReadTxnWarnings::default()` unless changed,
+	/// `None` turns them off along with tracking open `RoTxn`s for [`Env::read_txns`]
+	#[must_use]
+	pub fn read_txn_warnings(mut self, warnings: Option<ReadTxnWarnings>) -> Self {
+		self.read_txn_warnings = warnings;
+		self
+	}
+
+	/// Keeps up to `size` dropped `RoTxn`s reset, so `Env::read_tx` can renew them instead of beginning new ones.
+	/// Each of them keeps its slot in the reader lock table, so `maxreaders` has to leave room for them.
+	/// 0, so off, by default.
+	#[must_use]
+	pub fn read_txn_pool(mut self, size: usize) -> Self {
+		self.read_txn_pool_size = size;
+		self
+	}
+
+	/// Runs `Env::write` and `Env::try_write` jobs that queue up while another write holds the lock
+	/// back to back in one tx, up to `max_jobs` at a time, so they share a single commit and sync.
+	/// Each job runs in a nested tx, so a failing `try_write` job doesn't affect the others,
+	/// but a failed commit fails every job in the batch.
+	/// Can't be combined with `write_map`.
+	#[must_use]
+	pub fn group_commit(mut self, max_jobs: usize) -> Self {
+		self.group_commit = Some(max_jobs);
+		self
+	}
+
+	/// Runs the blocking parts of async writes and copies, by default on `tokio::task::spawn_blocking`,
+	/// or without the `tokio` feature on threads that are kept around for 10s after a job to be reused.
+	/// e.g. `.spawn_blocking(|job| blocking::unblock(job).detach())` for smol
+	/// Jobs have to be able to run concurrently: [`Env::backup_to_writer`] waits on one job from another,
+	/// so running them inline or on a single thread deadlocks.
+	#[must_use]
+	pub fn spawn_blocking(mut self, spawn: impl Fn(BlockingJob) + Send + Sync + 'static) -> Self {
+		self.spawner = Box::new(spawn);
+		self
+	}
+
+	/// Grows the map when a write hits `MapFull`, up to `max_mapsize` bytes, then re-runs the write.
+	/// `max_mapsize` can't be smaller than the map and the growth can't be a no-op.
+	/// Resizing waits for this process' read txns to close and blocks new ones until it's done,
+	/// so a write made while the same thread holds a `RoTxn` fails with `MapFull` after a few seconds instead.
+	#[must_use]
+	pub fn map_growth(mut self, growth: MapGrowth, max_mapsize: usize) -> Self {
+		self.map_growth = Some((growth, max_mapsize));
+		self
+	}
+
 	#[must_use]
 	pub fn with<N: DbName>(mut self) -> Self {
-		self.dbs.push((N::NAME, N::flags() | N::Table::<'static, 'static, RwTxn>::flags()));
+		self.dbs.push((N::NAME.into(), N::flags() | N::Table::<'static, 'static, RwTxn>::flags()));
+		self.dbs.extend(N::Table::<'static, 'static, RwTxn>::extra_dbs(N::NAME));
+		self
+	}
+
+	/// Migrations are run in order during [`EnvBuilder::build`], each in its own `RwTxn`
+	/// which also bumps `MetaField::Version` in the `Meta` table.
+	/// The `n`th migration (starting from 0) takes the db from version `n` to `n + 1`,
+	/// so the list must only ever be appended to.
+	/// A fresh db is at version 0, a db at a version newer than the number of migrations is refused.
+	#[must_use]
+	pub fn migrations(mut self, migrations: Vec<Migration>) -> Self {
+		if !self.dbs.iter().any(|(name, _)| **name == *Meta::NAME) {
+			self = self.with::<Meta>();
+		}
+		self.migrations = Some(migrations);
 		self
 	}
 
 	#[throws]
-	pub fn build(self, path: &std::ffi::CStr) -> Env {
-		let flags =
-			lmdb_sys::MDB_NOMETASYNC | // maybe lose last transaction in case of a crash
-			lmdb_sys::MDB_NOTLS |      // don't use thread-local storage - read and write transactions can be on any thread, still at most 1 write tx
-			lmdb_sys::MDB_NORDAHEAD;   // don't readahead - useful when datasets are bigger than ram (does nothing on Windows)
+	pub fn build(mut self, path: &std::ffi::CStr) -> Env {
+		self.validate()?;
 
-		lmdb::env_set_maxdbs(self.raw_env, self.dbs.len() as u32)?;
-		
-		// 0664 is permissions for db folder on Unix - read/write/not execute
-		lmdb::env_open(self.raw_env, path, flags, 664)?;
+		let maxdbs = self.dbs.len() as u32;
+		// the env takes over the handle from here, and is dropped if opening fails, which closes it
+		let raw_env = std::mem::replace(&mut self.raw_env, std::ptr::null_mut());
+		let spawner = std::mem::replace(&mut self.spawner, Box::new(drop));
+		let mut env = Env { raw_env, dbs: HashMap::new(), write_lock: runtime::WriteLock::default(), spawner, map_growth: self.map_growth, readers: Readers::default(), read_txn_warnings: self.read_txn_warnings, read_txn_pool: Default::default(), read_txn_pool_size: self.read_txn_pool_size, batch: self.group_commit.map(batch::Batch::new), read_only: self.flags & lmdb_sys::MDB_RDONLY != 0, in_flight: Default::default() };
+		lmdb::env_set_maxdbs(env.raw_env, maxdbs)?;
+		lmdb::env_open(env.raw_env, path, self.flags, self.mode)?;
+		// only known once open, an existing db may already be bigger than `mapsize`
+		if let Some((_, max_mapsize)) = self.map_growth {
+			if max_mapsize < lmdb::env_info(env.raw_env)?.me_mapsize { culpa::throw!(Error::InvalidConfig("map_growth's max_mapsize is smaller than the map")); }
+		}
 
-		let mut env = Env { raw_env: self.raw_env, dbs: HashMap::new(), write_sema: tokio::sync::Semaphore::new(1) };
-		let db_create_tx = RwTxn { raw: lmdb::txn_begin(self.raw_env, 0)?, env: &env };
 		let mut dbs = HashMap::with_capacity(self.dbs.len());
-		for (name, flags) in self.dbs {
-			log::trace!("creating {}", unsafe { std::str::from_utf8_unchecked(name) });
-			dbs.insert(name, lmdb::dbi_open(db_create_tx.raw(), name, flags | DbFlags::Create));
+		if self.flags & lmdb_sys::MDB_RDONLY == 0 {
+			let db_create_tx = env.write_tx()?;
+			for (name, flags) in std::mem::take(&mut self.dbs) {
+				log::trace!("creating {}", String::from_utf8_lossy(&name));
+				let dbi = lmdb::dbi_open(db_create_tx.raw(), &name, flags | DbFlags::Create);
+				dbs.insert(name, dbi);
+			}
+			db_create_tx.commit()?;
+		} else {
+			// dbis opened in a read tx stay open only if it's committed
+			let db_open_tx = env.read_tx()?;
+			for (name, flags) in std::mem::take(&mut self.dbs) {
+				log::trace!("opening {}", String::from_utf8_lossy(&name));
+				let Some(dbi) = lmdb::dbi_open_existing(db_open_tx.raw(), &name, flags)? else {
+					culpa::throw!(Error::MissingTable(String::from_utf8_lossy(name.strip_suffix(b"\0").unwrap_or(&name)).into_owned()));
+				};
+				dbs.insert(name, dbi);
+			}
+			db_open_tx.commit()?;
 		}
-		db_create_tx.commit()?;
 
 		env.dbs = dbs;
+
+		if let Some(migrations) = self.migrations.take() {
+			env.migrate(migrations)?;
+		}
+
 		env
 	}
+
+	/// Opens an existing env without ever starting a write tx, e.g. from another process or on a read-only mount.
+	/// All tables registered with [`EnvBuilder::with`] have to already exist.
+	#[throws]
+	pub fn build_read_only(self, path: &std::ffi::CStr) -> ReadOnlyEnv {
+		ReadOnlyEnv(self.flag(lmdb_sys::MDB_RDONLY, true).build(path)?)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, Table, AssocTable, Error, test_env};
+	use super::{MapGrowth, SharedReadOnlyEnv, SharedEnv};
+	use crate::{Env, Transaction, test_env::path};
+
+	#[derive(DbName)]
+	#[table(AssocTable<'tx, TX, u32, u32>)]
+	struct Numbers;
+
+	#[derive(DbName)]
+	#[table(AssocTable<'tx, TX, u32, Vec<u8>>)]
+	struct Blobs;
+
+	// about 3MiB
+	fn put_blobs(tx: &crate::RwTxn) -> Result<(), Error> {
+		for key in 0..100 { Blobs::get(tx).put(&key, &vec![key as u8; 32 << 10])?; }
+		Ok(())
+	}
+
+	#[test]
+	fn version_without_migrations() {
+		let env = test_env::build(|b| b.with::<Numbers>());
+		assert_eq!(env.version().unwrap(), 0);
+	}
+
+	#[test]
+	fn migrations_bump_version() {
+		let env = test_env::build(|b| b.with::<Numbers>().migrations(vec![
+			Box::new(|tx| { Numbers::get(tx).put(&1, &1)?; Ok::<_, Error>(()) }),
+			Box::new(|tx| { Numbers::get(tx).put(&2, &2)?; Ok::<_, Error>(()) }),
+		]));
+		assert_eq!(env.version().unwrap(), 2);
+		assert_eq!(Numbers::get(&env.read_tx().unwrap()).entries().unwrap(), 2);
+	}
+
+	#[test]
+	fn useless_map_growth_is_rejected() {
+		let growth = |growth, max_mapsize| test_env::try_build(|b| b.mapsize(1 << 20).unwrap().map_growth(growth, max_mapsize)).map(|_| ());
+		assert!(matches!(growth(MapGrowth::Add(0), 1 << 30), Err(Error::InvalidConfig(_))));
+		assert!(matches!(growth(MapGrowth::Multiply(0), 1 << 30), Err(Error::InvalidConfig(_))));
+		assert!(matches!(growth(MapGrowth::Multiply(1), 1 << 30), Err(Error::InvalidConfig(_))));
+		assert!(matches!(growth(MapGrowth::Multiply(2), 1 << 19), Err(Error::InvalidConfig(_))));
+		assert!(growth(MapGrowth::Multiply(2), 1 << 20).is_ok());
+		assert!(growth(MapGrowth::Add(1 << 20), 1 << 30).is_ok());
+	}
+
+	#[tokio::test]
+	async fn shared_read_only_env() {
+		let env = test_env::build(|b| b.with::<Numbers>());
+		env.write_blocking(|tx| Numbers::get(tx).put(&1, &1)).unwrap().unwrap();
+		let dir = env.into_dir();
+
+		let env = SharedReadOnlyEnv::from(Env::builder().unwrap().with::<Numbers>().build_read_only(&path(&dir)).unwrap());
+		{
+			let tx = env.read_tx().unwrap();
+			assert_eq!(Numbers::get(&tx).entries().unwrap(), 1);
+			assert_eq!(tx.env().version().unwrap(), 0);
+		}
+		let copy = tempfile::tempdir().unwrap();
+		env.copy_to(&path(&copy), true).await.unwrap();
+		env.clone().close().await.unwrap_err();
+		env.close().await.unwrap();
+
+		let copy = Env::builder().unwrap().with::<Numbers>().build_read_only(&path(&copy)).unwrap();
+		assert_eq!(Numbers::get(&copy.read_tx().unwrap()).entries().unwrap(), 1);
+		copy.close().await.unwrap();
+	}
+
+	#[test]
+	fn info_and_readers() {
+		let env = test_env::build(|b| b.with::<Numbers>());
+		env.write_blocking(|tx| Numbers::get(tx).put(&1, &1)).unwrap().unwrap();
+		let info = env.info().unwrap();
+		assert!(info.last_txn_id > 0);
+		assert!(info.readers_in_use <= info.max_readers);
+		// the main db has an entry per table
+		assert!(env.stat().unwrap().entries >= 1);
+		assert!(env.read_txns().is_empty());
+
+		let tx = env.read_tx().unwrap();
+		let txns = env.read_txns();
+		assert_eq!(txns.len(), 1);
+		assert_eq!(txns[0].txn_id, info.last_txn_id);
+		let pid = i32::try_from(std::process::id()).unwrap();
+		assert!(env.readers().unwrap().iter().any(|reader| reader.pid == pid && reader.txn_id == Some(info.last_txn_id)));
+		drop(tx);
+		assert!(env.read_txns().is_empty());
+		assert!(env.readers().unwrap().iter().all(|reader| reader.txn_id.is_none()));
+	}
+
+	#[test]
+	fn pooled_read_txn_is_renewed() {
+		let env = test_env::build(|b| b.with::<Numbers>().read_txn_pool(1));
+		env.write_blocking(|tx| Numbers::get(tx).put(&1, &1)).unwrap().unwrap();
+		let raw = env.read_tx().unwrap().raw();
+		assert_eq!(env.read_txn_pool.lock().unwrap().len(), 1);
+
+		env.write_blocking(|tx| Numbers::get(tx).put(&2, &2)).unwrap().unwrap();
+		let tx = env.read_tx().unwrap();
+		assert_eq!(tx.raw(), raw);
+		assert!(env.read_txn_pool.lock().unwrap().is_empty());
+		assert_eq!(Numbers::get(&tx).entries().unwrap(), 2);
+		assert_eq!(env.read_txns()[0].txn_id, env.info().unwrap().last_txn_id);
+	}
+
+	#[test]
+	fn read_txns_untracked_without_warnings() {
+		let env = test_env::build(|b| b.with::<Numbers>().read_txn_warnings(None));
+		let _tx = env.read_tx().unwrap();
+		assert!(env.read_txns().is_empty());
+	}
+
+	// two handles on one env stand in for another process
+	fn grown_elsewhere() -> (Env, Env, tempfile::TempDir) {
+		let dir = tempfile::tempdir().unwrap();
+		let small = Env::builder().unwrap().with::<Blobs>().mapsize(1 << 20).unwrap().build(&path(&dir)).unwrap();
+		let big = Env::builder().unwrap().with::<Blobs>().mapsize(16 << 20).unwrap().build(&path(&dir)).unwrap();
+		(small, big, dir)
+	}
+
+	#[test]
+	fn read_tx_adopts_grown_map() {
+		let (small, big, _dir) = grown_elsewhere();
+		big.write_blocking(put_blobs).unwrap().unwrap();
+		assert_eq!(Blobs::get(&small.read_tx().unwrap()).entries().unwrap(), 100);
+		assert!(small.info().unwrap().map_size >= 4 << 20);
+	}
+
+	#[test]
+	fn read_tx_cant_adopt_grown_map_while_holding_a_reader() {
+		let (small, big, _dir) = grown_elsewhere();
+		let held = small.read_tx().unwrap();
+		big.write_blocking(put_blobs).unwrap().unwrap();
+		assert!(matches!(small.read_tx(), Err(Error::Lmdb(crate::lmdb::Error::MapResized))));
+		drop(held);
+		assert_eq!(Blobs::get(&small.read_tx().unwrap()).entries().unwrap(), 100);
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn backup_to_vec() {
+		struct Backup(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+		impl std::io::Write for Backup {
+			fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.0.lock().unwrap().write(buf) }
+			fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+		}
+
+		let (env, _dir) = test_env::build(|b| b.with::<Numbers>()).shared();
+		env.write(|tx| Numbers::get(tx).put(&1, &2)).await.unwrap().unwrap();
+		let backup = std::sync::Arc::<std::sync::Mutex<Vec<u8>>>::default();
+		env.backup_to_writer(Backup(backup.clone())).await.unwrap();
+		env.close().await.unwrap();
+
+		let restored = tempfile::tempdir().unwrap();
+		std::fs::write(restored.path().join("data.mdb"), &*backup.lock().unwrap()).unwrap();
+		let env = Env::builder().unwrap().with::<Numbers>().build_read_only(&path(&restored)).unwrap();
+		assert_eq!(Numbers::get(&env.read_tx().unwrap()).get(&1).unwrap().unwrap().to_native(), 2);
+		env.close().await.unwrap();
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn close_waits_for_queued_writes() {
+		for _ in 0..20 {
+			let (env, dir) = test_env::build(|b| b.with::<Numbers>()).shared();
+			let writes = (0..20).map(|i| tokio::spawn(env.write(move |tx| Numbers::get(tx).put(&i, &i)))).collect::<Vec<_>>();
+			// not polled yet, but still waited for
+			let unpolled = env.write(|tx| Numbers::get(tx).put(&100, &100));
+			let closing = tokio::spawn(env.close());
+			unpolled.await.unwrap().unwrap();
+			closing.await.unwrap().unwrap();
+			for write in writes { write.await.unwrap().unwrap().unwrap(); }
+
+			let env = Env::builder().unwrap().with::<Numbers>().build(&path(&dir)).unwrap();
+			assert_eq!(Numbers::get(&env.read_tx().unwrap()).entries().unwrap(), 21);
+		}
+	}
+
+	#[tokio::test]
+	async fn close_refuses_other_clones() {
+		let (env, _dir) = test_env::build(|b| b.with::<Numbers>()).shared();
+		let clone = SharedEnv::clone(&env);
+		assert!(matches!(env.close().await, Err(Error::EnvInUse(_))));
+		clone.close().await.unwrap();
+	}
+
+	#[tokio::test]
+	async fn map_grows_and_job_reruns() {
+		let (env, _dir) = test_env::build(|b| b.with::<Blobs>().mapsize(1 << 20).unwrap().map_growth(MapGrowth::Multiply(2), 16 << 20)).shared();
+		let runs = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+		env.write({
+			let runs = runs.clone();
+			move |tx| { runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst); put_blobs(tx) }
+		}).await.unwrap().unwrap();
+		assert!(runs.load(std::sync::atomic::Ordering::SeqCst) > 1);
+		assert!(env.info().unwrap().map_size > 1 << 20);
+		let tx = env.read_tx().unwrap();
+		assert_eq!(Blobs::get(&tx).entries().unwrap(), 100);
+		assert_eq!(Blobs::get(&tx).get(&99).unwrap().unwrap().len(), 32 << 10);
+	}
+
+	#[tokio::test]
+	async fn map_full_once_grown_to_the_max() {
+		let (env, _dir) = test_env::build(|b| b.with::<Blobs>().mapsize(1 << 20).unwrap().map_growth(MapGrowth::Multiply(2), 2 << 20)).shared();
+		let res = env.try_write(put_blobs).await.unwrap();
+		assert!(matches!(res, Err(Error::Lmdb(crate::lmdb::Error::MapFull))));
+		assert_eq!(env.info().unwrap().map_size, 2 << 20);
+		assert_eq!(Blobs::get(&env.read_tx().unwrap()).entries().unwrap(), 0);
+	}
 }
@@ -0,0 +1,207 @@
+use culpa::throws;
+use crate::{lmdb, Error, RwTxn, Transaction};
+use super::Env;
+
+// group commit, see `EnvBuilder::group_commit`
+pub(super) struct Batch {
+	queue: std::sync::Mutex<Vec<(u64, Box<dyn BatchedJob>)>>,
+	next_id: std::sync::atomic::AtomicU64,
+	max_jobs: usize,
+}
+
+impl Batch {
+	pub(super) fn new(max_jobs: usize) -> Self {
+		Self { queue: Default::default(), next_id: Default::default(), max_jobs }
+	}
+}
+
+// takes the job back out of the queue if its caller gives up before a batch picks it up
+struct Dequeue<'batch> {
+	batch: &'batch Batch,
+	id: u64,
+}
+
+impl Drop for Dequeue<'_> {
+	fn drop(&mut self) {
+		let mut queue = self.batch.queue.lock().unwrap();
+		let job = queue.iter().position(|&(id, _)| id == self.id).map(|i| queue.remove(i));
+		drop(queue);
+		// the job's captures are dropped outside the lock
+		drop(job);
+	}
+}
+
+trait BatchedJob: Send {
+	// runs the job in a nested tx of the batch's tx and keeps its result
+	fn run(&mut self, tx: &RwTxn);
+	// sends the kept result, or the error the whole batch failed with
+	fn deliver(self: Box<Self>, batch: Result<(), lmdb::Error>);
+}
+
+struct Queued<Res, Job> {
+	job: Job,
+	// whether to commit the job's nested tx
+	keep: fn(&Res) -> bool,
+	// a panicking job only loses its own nested tx, the panic resumes in whoever queued it
+	res: Option<std::thread::Result<Result<Res, Error>>>,
+	send: std::sync::mpsc::SyncSender<std::thread::Result<Result<Res, Error>>>,
+}
+
+impl<Res, Job> BatchedJob for Queued<Res, Job> where
+	Res: Send,
+	Job: FnMut(&RwTxn) -> Res + Send,
+{
+	fn run(&mut self, tx: &RwTxn) {
+		let (job, keep) = (&mut self.job, self.keep);
+		// a job that fails leaves the batch's tx as it was, so the other jobs still commit
+		let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| tx.nested(|child| {
+			let res = job(child);
+			if keep(&res) { Ok(res) } else { Err(res) }
+		})));
+		self.res = Some(res.map(|res| res.map(|res| res.unwrap_or_else(|res| res))));
+	}
+
+	fn deliver(self: Box<Self>, batch: Result<(), lmdb::Error>) {
+		let res = match batch {
+			Ok(()) => self.res.expect("batched job wasn't run"),
+			Err(e) => Ok(Err(e.into())),
+		};
+		// the caller might be gone already
+		let _ = self.send.send(res);
+	}
+}
+
+impl Env {
+	// queues the job, then whoever holds the write lock runs everything queued so far in one tx
+	#[throws]
+	pub(super) async fn write_batched<Res, Job>(env: impl super::Handle, job: Job, keep: fn(&Res) -> bool) -> Res where
+		Res: Send + 'static,
+		Job: (FnMut(&RwTxn) -> Res) + Send + 'static,
+	{
+		let batch = env.batch.as_ref().expect("group commit isn't enabled");
+		let (send, recv) = std::sync::mpsc::sync_channel(1);
+		let id = batch.next_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+		batch.queue.lock().unwrap().push((id, Box::new(Queued { job, keep, res: None, send })));
+		let _dequeue = Dequeue { batch, id };
+
+		let _lock = env.write_lock.lock().await;
+		let now = std::time::Instant::now();
+		// an earlier batch might've run this job already, or the queue might be longer than a batch
+		let res = loop {
+			match recv.try_recv() {
+				Ok(res) => break res,
+				Err(std::sync::mpsc::TryRecvError::Empty) => {},
+				Err(std::sync::mpsc::TryRecvError::Disconnected) => panic!("the batch with this job panicked"),
+			}
+			let jobs = {
+				let mut queue = batch.queue.lock().unwrap();
+				let len = queue.len().min(batch.max_jobs);
+				queue.drain(..len).map(|(_, job)| job).collect::<Vec<_>>()
+			};
+			let batch_env = env.clone();
+			env.spawn_blocking(move || batch_env.run_batch(jobs)).await;
+		};
+		drop(_lock);
+		super::complain_about_lock_hold(now);
+		env.complain_about_old_readers();
+		res.unwrap_or_else(|panic| std::panic::resume_unwind(panic))?
+	}
+
+	// re-runs all the jobs with a bigger map if one hits MapFull and `EnvBuilder::map_growth` allows it
+	fn run_batch(&self, mut jobs: Vec<Box<dyn BatchedJob>>) {
+		let batch = (|| -> Result<(), lmdb::Error> {
+			loop {
+				let tx = self.write_tx()?;
+				for job in &mut jobs { job.run(&tx); }
+				if tx.hit_map_full() {
+					if let Some(mapsize) = self.grown_mapsize()? {
+						tx.abort();
+						self.resize_map(mapsize)?;
+						continue;
+					}
+				}
+				match tx.commit_lmdb() {
+					Ok(()) => return Ok(()),
+					Err(lmdb::Error::MapFull) => match self.grown_mapsize()? {
+						Some(mapsize) => self.resize_map(mapsize)?,
+						None => culpa::throw!(lmdb::Error::MapFull),
+					},
+					Err(e) => culpa::throw!(e),
+				}
+			}
+		})();
+		if jobs.len() > 1 { log::trace!("committed {} batched writes", jobs.len()); }
+		for job in jobs { job.deliver(batch); }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, AssocTable, test_env};
+
+	#[derive(DbName)]
+	#[table(AssocTable<'tx, TX, u32, u32>)]
+	struct Numbers;
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn failing_jobs_only_lose_their_own_writes() {
+		let (env, _dir) = test_env::build(|b| b.with::<Numbers>().group_commit(8)).shared();
+		let (started, is_started) = std::sync::mpsc::channel();
+		let (release, released) = std::sync::mpsc::channel::<()>();
+		// holds the lock until the others are queued up into one batch
+		let first = tokio::spawn(env.write(move |tx| {
+			started.send(()).unwrap();
+			released.recv().unwrap();
+			Numbers::get(tx).put(&0, &0)
+		}));
+		is_started.recv().unwrap();
+
+		let ok = tokio::spawn(env.write(|tx| Numbers::get(tx).put(&1, &1)));
+		let failing = tokio::spawn(env.try_write(|tx| { Numbers::get(tx).put(&2, &2).unwrap(); Err::<(), _>("failed") }));
+		let panicking = tokio::spawn(env.write(|tx| { Numbers::get(tx).put(&3, &3).unwrap(); panic!("job panicked") }));
+		let also_ok = tokio::spawn(env.write(|tx| Numbers::get(tx).put(&4, &4)));
+		while env.batch.as_ref().unwrap().queue.lock().unwrap().len() < 4 { tokio::task::yield_now().await; }
+		release.send(()).unwrap();
+
+		first.await.unwrap().unwrap().unwrap();
+		ok.await.unwrap().unwrap().unwrap();
+		assert_eq!(failing.await.unwrap().unwrap(), Err("failed"));
+		assert!(panicking.await.unwrap_err().is_panic());
+		also_ok.await.unwrap().unwrap().unwrap();
+
+		let tx = env.read_tx().unwrap();
+		let numbers = Numbers::get(&tx);
+		for (key, kept) in [(0, true), (1, true), (2, false), (3, false), (4, true)] {
+			assert_eq!(numbers.get(&key).unwrap().is_some(), kept, "key {key}");
+		}
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn dropped_write_never_runs() {
+		let (env, _dir) = test_env::build(|b| b.with::<Numbers>().group_commit(8)).shared();
+		let (started, is_started) = std::sync::mpsc::channel();
+		let (release, released) = std::sync::mpsc::channel::<()>();
+		let first = tokio::spawn(env.write(move |tx| {
+			started.send(()).unwrap();
+			released.recv().unwrap();
+			Numbers::get(tx).put(&0, &0)
+		}));
+		is_started.recv().unwrap();
+
+		let ran = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+		let dropped = tokio::spawn(env.write({
+			let ran = ran.clone();
+			move |tx| { ran.store(true, std::sync::atomic::Ordering::SeqCst); Numbers::get(tx).put(&1, &1) }
+		}));
+		while env.batch.as_ref().unwrap().queue.lock().unwrap().is_empty() { tokio::task::yield_now().await; }
+		dropped.abort();
+		assert!(dropped.await.unwrap_err().is_cancelled());
+		assert!(env.batch.as_ref().unwrap().queue.lock().unwrap().is_empty());
+
+		release.send(()).unwrap();
+		first.await.unwrap().unwrap().unwrap();
+		env.write(|tx| Numbers::get(tx).put(&2, &2)).await.unwrap().unwrap();
+		assert!(!ran.load(std::sync::atomic::Ordering::SeqCst));
+		assert!(Numbers::get(&env.read_tx().unwrap()).get(&1).unwrap().is_none());
+	}
+}
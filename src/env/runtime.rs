@@ -0,0 +1,454 @@
+// the runtime-agnostic bits: the write lock, and running blocking jobs on whatever executor the user has
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use super::{Env, Handle};
+
+/// a job for the executor set with [`super::EnvBuilder::spawn_blocking`]
+pub type BlockingJob = Box<dyn FnOnce() + Send>;
+pub(super) type Spawner = Box<dyn Fn(BlockingJob) + Send + Sync>;
+
+#[cfg(feature = "tokio")]
+pub(super) fn default_spawner() -> Spawner {
+	Box::new(|job| { tokio::task::spawn_blocking(job); })
+}
+
+#[cfg(not(feature = "tokio"))]
+pub(super) fn default_spawner() -> Spawner {
+	let pool = Arc::new(ThreadPool::default());
+	Box::new(move |job| ThreadPool::run(&pool, job))
+}
+
+// reuses idle threads, but starts another one if none is idle, since a job can wait on another
+#[cfg(not(feature = "tokio"))]
+#[derive(Default)]
+struct ThreadPool {
+	idle: Mutex<Vec<(std::thread::ThreadId, std::sync::mpsc::Sender<BlockingJob>)>>,
+}
+
+#[cfg(not(feature = "tokio"))]
+impl ThreadPool {
+	// how long a thread waits for another job before it exits
+	const KEEP_ALIVE: std::time::Duration = std::time::Duration::from_secs(10);
+
+	fn run(pool: &Arc<Self>, job: BlockingJob) {
+		let idle = pool.idle.lock().unwrap().pop();
+		if let Some((_, send)) = idle {
+			// an idle thread only exits while it's still in `idle`, so it gets this
+			send.send(job).unwrap();
+			return;
+		}
+		let (send, recv) = std::sync::mpsc::channel();
+		let pool = pool.clone();
+		std::thread::spawn(move || {
+			let mut job = Some(job);
+			while let Some(next) = job.take() {
+				next();
+				pool.idle.lock().unwrap().push((std::thread::current().id(), send.clone()));
+				job = match recv.recv_timeout(Self::KEEP_ALIVE) {
+					Ok(next) => Some(next),
+					Err(_) => {
+						let mut idle = pool.idle.lock().unwrap();
+						let Some(i) = idle.iter().position(|&(thread, _)| thread == std::thread::current().id()) else {
+							// `run` took this thread right after the timeout, so its job is on the way
+							drop(idle);
+							job = recv.recv().ok();
+							continue;
+						};
+						idle.swap_remove(i);
+						None
+					},
+				};
+			}
+		});
+	}
+}
+
+// an async mutex that can also be locked from sync code
+#[derive(Default)]
+pub(super) struct WriteLock {
+	state: Mutex<LockState>,
+	// wakes `blocking_lock` callers, async ones are woken through their wakers
+	unlocked: Condvar,
+}
+
+#[derive(Default)]
+struct LockState {
+	locked: bool,
+	next_id: u64,
+	waiters: VecDeque<(u64, Waker)>,
+}
+
+pub(super) struct WriteGuard<'lock>(&'lock WriteLock);
+
+pub(super) struct Lock<'lock> {
+	lock: &'lock WriteLock,
+	// set while queued or woken but not yet polled
+	id: Option<u64>,
+}
+
+impl WriteLock {
+	pub(super) fn lock(&self) -> Lock<'_> {
+		Lock { lock: self, id: None }
+	}
+
+	pub(super) fn blocking_lock(&self) -> WriteGuard<'_> {
+		let mut state = self.state.lock().unwrap();
+		while state.locked { state = self.unlocked.wait(state).unwrap(); }
+		state.locked = true;
+		WriteGuard(self)
+	}
+
+	// None if it's still locked after `timeout`
+	pub(super) fn blocking_lock_timeout(&self, timeout: std::time::Duration) -> Option<WriteGuard<'_>> {
+		let state = self.state.lock().unwrap();
+		let (mut state, _) = self.unlocked.wait_timeout_while(state, timeout, |state| state.locked).unwrap();
+		if state.locked { return None; }
+		state.locked = true;
+		Some(WriteGuard(self))
+	}
+}
+
+impl<'lock> Future for Lock<'lock> {
+	type Output = WriteGuard<'lock>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WriteGuard<'lock>> {
+		let lock = self.lock;
+		let mut state = lock.state.lock().unwrap();
+		let queued = self.id.and_then(|id| state.waiters.iter().position(|&(x, _)| x == id));
+		if !state.locked {
+			if let Some(i) = queued { state.waiters.remove(i); }
+			state.locked = true;
+			self.id = None;
+			return Poll::Ready(WriteGuard(lock));
+		}
+		if let Some(i) = queued {
+			state.waiters[i].1.clone_from(cx.waker());
+		} else {
+			let id = state.next_id;
+			state.next_id += 1;
+			state.waiters.push_back((id, cx.waker().clone()));
+			self.id = Some(id);
+		}
+		Poll::Pending
+	}
+}
+
+impl Drop for Lock<'_> {
+	fn drop(&mut self) {
+		let Some(id) = self.id else { return; };
+		let mut state = self.lock.state.lock().unwrap();
+		if let Some(i) = state.waiters.iter().position(|&(x, _)| x == id) {
+			state.waiters.remove(i);
+		} else if !state.locked {
+			// woken but dropped before taking the lock, so pass the wakeup on
+			if let Some((_, waker)) = state.waiters.pop_front() { waker.wake(); }
+		}
+	}
+}
+
+impl Drop for WriteGuard<'_> {
+	fn drop(&mut self) {
+		let mut state = self.0.state.lock().unwrap();
+		state.locked = false;
+		let waiter = state.waiters.pop_front();
+		drop(state);
+		// whoever gets there first takes the lock, the other waits again
+		if let Some((_, waker)) = waiter { waker.wake(); }
+		self.0.unlocked.notify_one();
+	}
+}
+
+// counts writes and copies holding a `SharedEnv` clone, so `SharedEnv::close` can wait for them
+#[derive(Default)]
+pub(super) struct InFlight {
+	state: Mutex<InFlightState>,
+}
+
+#[derive(Default)]
+struct InFlightState {
+	jobs: usize,
+	idle: Vec<Waker>,
+}
+
+// an env handle counted as in flight until it's dropped
+pub(super) struct Tracked<H: Handle> {
+	env: Option<H>,
+	in_flight: Arc<InFlight>,
+}
+
+pub(super) struct Idle<'a>(&'a InFlight);
+
+impl InFlight {
+	pub(super) fn jobs(&self) -> usize {
+		self.state.lock().unwrap().jobs
+	}
+
+	// resolves once nothing is in flight
+	pub(super) fn idle(&self) -> Idle<'_> {
+		Idle(self)
+	}
+
+	fn start(&self) {
+		self.state.lock().unwrap().jobs += 1;
+	}
+
+	fn finish(&self) {
+		let mut state = self.state.lock().unwrap();
+		state.jobs -= 1;
+		if state.jobs > 0 { return; }
+		let idle = std::mem::take(&mut state.idle);
+		drop(state);
+		for waker in idle { waker.wake(); }
+	}
+}
+
+impl Future for Idle<'_> {
+	type Output = ();
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+		let mut state = self.0.state.lock().unwrap();
+		if state.jobs == 0 { return Poll::Ready(()); }
+		if !state.idle.iter().any(|waker| waker.will_wake(cx.waker())) { state.idle.push(cx.waker().clone()); }
+		Poll::Pending
+	}
+}
+
+impl<H: Handle> Clone for Tracked<H> {
+	fn clone(&self) -> Self {
+		self.in_flight.start();
+		Self { env: self.env.clone(), in_flight: self.in_flight.clone() }
+	}
+}
+
+impl<H: Handle> std::ops::Deref for Tracked<H> {
+	type Target = Env;
+	fn deref(&self) -> &Env { self.env.as_ref().unwrap() }
+}
+
+impl<H: Handle> Drop for Tracked<H> {
+	fn drop(&mut self) {
+		// the clone goes first, so `close` never sees more clones than jobs
+		drop(self.env.take());
+		self.in_flight.finish();
+	}
+}
+
+impl Env {
+	pub(super) fn track<H: Handle>(env: H) -> Tracked<H> {
+		env.in_flight.start();
+		let in_flight = env.in_flight.clone();
+		Tracked { env: Some(env), in_flight }
+	}
+}
+
+// the job's result, or its panic
+struct Slot<T> {
+	res: Option<std::thread::Result<T>>,
+	done: bool,
+	waker: Option<Waker>,
+}
+
+// marks the slot done when the job finishes, or when the executor drops it without running it
+struct Finish<T>(Arc<Mutex<Slot<T>>>);
+
+impl<T> Drop for Finish<T> {
+	fn drop(&mut self) {
+		let mut slot = self.0.lock().unwrap();
+		slot.done = true;
+		let waker = slot.waker.take();
+		drop(slot);
+		if let Some(waker) = waker { waker.wake(); }
+	}
+}
+
+pub(super) struct Blocking<T>(Arc<Mutex<Slot<T>>>);
+
+impl<T> Future for Blocking<T> {
+	type Output = T;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+		let mut slot = self.0.lock().unwrap();
+		if !slot.done {
+			slot.waker = Some(cx.waker().clone());
+			return Poll::Pending;
+		}
+		match slot.res.take() {
+			Some(Ok(res)) => Poll::Ready(res),
+			Some(Err(panic)) => { drop(slot); std::panic::resume_unwind(panic) },
+			None => panic!("blocking job was dropped without running"),
+		}
+	}
+}
+
+impl Env {
+	// runs the job on the blocking executor, its panics resume in whoever awaits it
+	pub(super) fn spawn_blocking<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> Blocking<T> {
+		let slot = Arc::new(Mutex::new(Slot { res: None, done: false, waker: None }));
+		let finish = Finish(slot.clone());
+		(self.spawner)(Box::new(move || {
+			let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+			finish.0.lock().unwrap().res = Some(res);
+		}));
+		Blocking(slot)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::WriteLock;
+	use std::future::Future;
+	use std::sync::Arc;
+	use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+	use std::task::{Context, Poll, Wake, Waker};
+	use std::time::Duration;
+
+	#[derive(Default)]
+	struct Woken(AtomicBool);
+
+	impl Wake for Woken {
+		fn wake(self: Arc<Self>) { self.0.store(true, Ordering::SeqCst); }
+	}
+
+	impl Woken {
+		fn take(&self) -> bool { self.0.swap(false, Ordering::SeqCst) }
+	}
+
+	// panics if two holders ever overlap
+	#[derive(Default)]
+	struct Exclusive { held: AtomicBool, times: AtomicUsize }
+
+	impl Exclusive {
+		fn hold(&self) {
+			assert!(!self.held.swap(true, Ordering::SeqCst), "lock held twice");
+			std::thread::yield_now();
+			self.times.fetch_add(1, Ordering::SeqCst);
+			self.held.store(false, Ordering::SeqCst);
+		}
+	}
+
+	#[test]
+	fn dropped_waiter_passes_the_wakeup_on() {
+		let lock = WriteLock::default();
+		let (woken_a, woken_b) = (Arc::new(Woken::default()), Arc::new(Woken::default()));
+		let (waker_a, waker_b) = (Waker::from(woken_a.clone()), Waker::from(woken_b.clone()));
+		let guard = lock.blocking_lock();
+		let mut a = Box::pin(lock.lock());
+		let mut b = Box::pin(lock.lock());
+		assert!(a.as_mut().poll(&mut Context::from_waker(&waker_a)).is_pending());
+		assert!(b.as_mut().poll(&mut Context::from_waker(&waker_b)).is_pending());
+
+		drop(guard);
+		assert!(woken_a.take());
+		assert!(!woken_b.take());
+		// cancelled after being woken, but before taking the lock
+		drop(a);
+		assert!(woken_b.take());
+		assert!(matches!(b.as_mut().poll(&mut Context::from_waker(&waker_b)), Poll::Ready(_)));
+	}
+
+	#[test]
+	fn queued_waiter_dropped_leaves_the_queue() {
+		let lock = WriteLock::default();
+		let woken = Arc::new(Woken::default());
+		let waker = Waker::from(woken.clone());
+		let guard = lock.blocking_lock();
+		let mut a = Box::pin(lock.lock());
+		let mut b = Box::pin(lock.lock());
+		assert!(a.as_mut().poll(&mut Context::from_waker(&waker)).is_pending());
+		assert!(b.as_mut().poll(&mut Context::from_waker(&waker)).is_pending());
+		drop(a);
+		drop(guard);
+		assert!(woken.take());
+		assert!(matches!(b.as_mut().poll(&mut Context::from_waker(&waker)), Poll::Ready(_)));
+	}
+
+	#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+	async fn cancelled_lock_futures() {
+		let lock = Arc::new(WriteLock::default());
+		let exclusive = Arc::new(Exclusive::default());
+		let (mut tasks, mut must_hold) = (Vec::new(), 0);
+		for i in 0..400 {
+			let (lock, exclusive) = (lock.clone(), exclusive.clone());
+			tasks.push(tokio::spawn(async move {
+				if i % 2 == 0 {
+					let _guard = lock.lock().await;
+					exclusive.hold();
+				} else {
+					// gives up right away if the lock isn't free
+					tokio::select! {
+						biased;
+						_guard = lock.lock() => exclusive.hold(),
+						() = std::future::ready(()) => {},
+					}
+				}
+			}));
+			// and some are cancelled while queued
+			if i % 7 == 0 { tasks.pop().unwrap().abort(); } else if i % 2 == 0 { must_hold += 1; }
+		}
+		for task in tasks { let _ = task.await; }
+		assert!(exclusive.times.load(Ordering::SeqCst) >= must_hold);
+		assert!(lock.blocking_lock_timeout(Duration::ZERO).is_some());
+	}
+
+	#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+	async fn blocking_and_async_waiters() {
+		const ROUNDS: usize = 200;
+		let lock = Arc::new(WriteLock::default());
+		let exclusive = Arc::new(Exclusive::default());
+		let threads = (0..4).map(|_| {
+			let (lock, exclusive) = (lock.clone(), exclusive.clone());
+			std::thread::spawn(move || for _ in 0..ROUNDS {
+				let _guard = lock.blocking_lock();
+				exclusive.hold();
+			})
+		}).collect::<Vec<_>>();
+		let tasks = (0..4).map(|_| {
+			let (lock, exclusive) = (lock.clone(), exclusive.clone());
+			tokio::spawn(async move { for _ in 0..ROUNDS {
+				let _guard = lock.lock().await;
+				exclusive.hold();
+			} })
+		}).collect::<Vec<_>>();
+		for task in tasks { task.await.unwrap(); }
+		for thread in threads { thread.join().unwrap(); }
+		assert_eq!(exclusive.times.load(Ordering::SeqCst), 8 * ROUNDS);
+	}
+
+	#[test]
+	fn lock_timeout() {
+		let lock = WriteLock::default();
+		let guard = lock.blocking_lock();
+		assert!(lock.blocking_lock_timeout(Duration::from_millis(10)).is_none());
+		std::thread::scope(|s| {
+			let waiter = s.spawn(|| lock.blocking_lock_timeout(Duration::from_secs(5)).is_some());
+			std::thread::sleep(Duration::from_millis(10));
+			drop(guard);
+			assert!(waiter.join().unwrap());
+		});
+		assert!(lock.blocking_lock_timeout(Duration::ZERO).is_some());
+	}
+
+	#[cfg(not(feature = "tokio"))]
+	#[test]
+	fn thread_pool_reuses_idle_threads_and_runs_jobs_concurrently() {
+		use super::ThreadPool;
+		let pool = Arc::new(ThreadPool::default());
+		let (done, is_done) = std::sync::mpsc::channel();
+		let first = done.clone();
+		ThreadPool::run(&pool, Box::new(move || first.send(std::thread::current().id()).unwrap()));
+		let first = is_done.recv().unwrap();
+		while pool.idle.lock().unwrap().is_empty() { std::thread::yield_now(); }
+
+		// the first job only finishes once the second one runs, so they can't share a thread
+		let (started, is_started) = std::sync::mpsc::channel::<()>();
+		let waiting = done.clone();
+		ThreadPool::run(&pool, Box::new(move || { is_started.recv().unwrap(); waiting.send(std::thread::current().id()).unwrap(); }));
+		ThreadPool::run(&pool, Box::new(move || { started.send(()).unwrap(); done.send(std::thread::current().id()).unwrap(); }));
+		let (second, third) = (is_done.recv().unwrap(), is_done.recv().unwrap());
+		assert_ne!(second, third);
+		assert!(second == first || third == first);
+	}
+}
@@ -2,4 +2,10 @@
 pub enum Error {
 	#[error(transparent)] Lmdb(#[from] crate::lmdb::Error),
 	#[error(transparent)] Rkyv(#[from] rkyv::rancor::Error),
+	#[error(transparent)] Io(#[from] std::io::Error),
+	#[error("invalid env config: {0}")] InvalidConfig(&'static str),
+	#[error("table {0} doesn't exist")] MissingTable(String),
+	#[error(transparent)] IndexConflict(#[from] crate::secondary_index::Conflict),
+	#[error("env can't be closed, {0}")] EnvInUse(&'static str),
+	#[error("database is at version {db}, but only migrations up to version {known} are known")] UnknownVersion { db: u32, known: u32 },
 }
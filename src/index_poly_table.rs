@@ -65,7 +65,7 @@ impl<'tx, 'env: 'tx, TX> IndexPolyTable<'tx, TX> where
 	#[throws]
 	fn last_numeric_index(&self) -> Option<u64> {
 		lmdb::Cursor::open(self.tx, self.dbi)?
-			.get_with_u64_key(lmdb::CursorOpFlags::Last)
+			.get_with_u64_key(lmdb::CursorOpFlags::Last)?
 			.map(|(key, _)| key)
 	}
 
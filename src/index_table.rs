@@ -1,35 +1,49 @@
-use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, DbFlags};
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, DbFlags, secondary_index::{self, SecondaryIndex, SecondaryIndices}};
 use culpa::throws;
 use batadase_index::Index;
 use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
 
-pub struct IndexTable<'tx, TX, T> {
+/// `I` are the secondary indices kept in sync with the table, see [`secondary_index`]
+pub struct IndexTable<'tx, TX, T, I = ()> {
 	tx: &'tx TX,
 	dbi: lmdb_sys::MDB_dbi,
-	_pd: PhantomData<T>,
+	_pd: PhantomData<(T, I)>,
 }
 
-impl<'tx, 'env: 'tx, TX, T> Table<'tx, 'env, TX> for IndexTable<'tx, TX, T> where
+impl<'tx, 'env: 'tx, TX, T, I> Table<'tx, 'env, TX> for IndexTable<'tx, TX, T, I> where
 	TX: Transaction<'env>,
 	T: rkyv::Archive,
 	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	I: SecondaryIndices<T>,
 {
 	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
 	fn txn(&self) -> &TX { self.tx }
 	fn flags() -> enumflags2::BitFlags<DbFlags> { DbFlags::IntegerKey.into() }
+	fn extra_dbs(_name: &'static [u8]) -> Vec<(std::borrow::Cow<'static, [u8]>, enumflags2::BitFlags<DbFlags>)> {
+		I::dbs().into_iter().map(|(name, flags)| (name.into(), flags)).collect()
+	}
 	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
 		Self::build(tx, tx.env().db(name).unwrap())
 	}
 }
 
-impl<'tx, T> IndexTable<'tx, RwTxn<'tx>, T> where
-	T: for <'a> rkyv::Serialize<RkyvSer<'a>>,
+impl<'tx, T, I> IndexTable<'tx, RwTxn<'tx>, T, I> where
+	T: for <'a> rkyv::Serialize<RkyvSer<'a>> + 'tx,
 	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	I: SecondaryIndices<T>,
 {
+	/// fails with `Error::IndexConflict` without changing anything if a secondary index field is taken by another index
 	#[throws]
 	pub fn put(&self, index: Index<T>, t: &T) {
 		let mut index_bytes = u64::from(index).to_ne_bytes();
 		let mut value_bytes = rkyv::to_bytes(t)?;
+		if !I::EMPTY {
+			let archived = rkyv::access::<rkyv::Archived<T>, _>(&value_bytes)?;
+			I::check(self.tx, index, archived)?;
+			self.remove_secondary(index)?;
+			I::insert(self.tx, index, archived)?;
+		}
 		lmdb::put(self.tx, self.dbi, &mut index_bytes, &mut value_bytes)?;
 	}
 
@@ -43,17 +57,51 @@ impl<'tx, T> IndexTable<'tx, RwTxn<'tx>, T> where
 	#[throws]
 	pub fn delete_index(&self, index: Index<T>) -> bool {
 		let mut index_bytes = u64::from(index).to_ne_bytes();
+		if !I::EMPTY { self.remove_secondary(index)?; }
 		lmdb::del(self.tx, self.dbi, &mut index_bytes)?
 	}
 
 	#[throws]
-	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi)?; }
+	pub fn clear(&self) {
+		I::clear(self.tx)?;
+		lmdb::drop(self.tx, self.dbi)?;
+	}
+
+	/// rebuilds the secondary indices from scratch, e.g. after adding one to a table with existing values
+	/// fails with `Error::IndexConflict` if two values share a field
+	#[throws]
+	pub fn reindex(&self) {
+		I::clear(self.tx)?;
+		// values are copied out because returned pointers don't survive writes to the tx
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
+		let mut values = Vec::new();
+		while let Some((key_u64, value_bytes)) = cursor.get_with_u64_key(lmdb::CursorOpFlags::Next)? {
+			values.push((Index::from(key_u64), value_bytes.to_vec()));
+		}
+		drop(cursor);
+		for (index, value_bytes) in values {
+			let archived = rkyv::access::<rkyv::Archived<T>, _>(&value_bytes)?;
+			I::check(self.tx, index, archived)?;
+			I::insert(self.tx, index, archived)?;
+		}
+	}
+
+	// removes fields pointing to the value currently at index
+	#[throws]
+	fn remove_secondary(&self, index: Index<T>) {
+		let mut index_bytes = u64::from(index).to_ne_bytes();
+		let Some(old_bytes) = lmdb::get(self.tx, self.dbi, &mut index_bytes)? else { return; };
+		// copied because returned pointers don't survive writes to the tx
+		let old_bytes = old_bytes.to_vec();
+		I::remove(self.tx, index, rkyv::access::<rkyv::Archived<T>, _>(&old_bytes)?)?;
+	}
 }
 
-impl<'tx, 'env: 'tx, TX, T> IndexTable<'tx, TX, T> where
+impl<'tx, 'env: 'tx, TX, T, I> IndexTable<'tx, TX, T, I> where
 	TX: Transaction<'env>,
 	T: rkyv::Archive,
 	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	I: SecondaryIndices<T>,
 {
 	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self {
 		Self { tx, dbi, _pd: PhantomData }
@@ -66,37 +114,237 @@ impl<'tx, 'env: 'tx, TX, T> IndexTable<'tx, TX, T> where
 		Some(rkyv::access::<rkyv::Archived<T>, _>(value_bytes)?)
 	}
 
+	#[throws]
+	pub fn by_field<S: SecondaryIndex<T>>(&self, field: &S::Field) -> Option<Index<T>> where
+		T: 'tx,
+	{
+		secondary_index::table::<TX, S, T>(self.tx)?.get(field)?.map(Index::from)
+	}
+
+	#[throws]
+	pub fn first(&self) -> Option<(Index<T>, &'tx rkyv::Archived<T>)> {
+		let Some((key_u64, value_bytes)) = lmdb::Cursor::open(self.tx, self.dbi)?.get_with_u64_key(lmdb::CursorOpFlags::First)? else { return None; };
+		Some((Index::from(key_u64), rkyv::access::<rkyv::Archived<T>, _>(value_bytes)?))
+	}
+
 	#[throws]
 	pub fn last(&self) -> Option<(Index<T>, &'tx rkyv::Archived<T>)> {
-		let Some((key_u64, value_bytes)) = lmdb::Cursor::open(self.tx, self.dbi)?.get_with_u64_key(lmdb::CursorOpFlags::Last) else { return None; };
+		let Some((key_u64, value_bytes)) = lmdb::Cursor::open(self.tx, self.dbi)?.get_with_u64_key(lmdb::CursorOpFlags::Last)? else { return None; };
 		Some((Index::from(key_u64), rkyv::access::<rkyv::Archived<T>, _>(value_bytes)?))
 	}
 
+	/// double-ended, so `.rev()` and `.next_back()` work too
+	/// stops after the first error
+	#[throws]
+	pub fn range<B: RangeBounds<Index<T>>>(&self, range: B) -> impl DoubleEndedIterator<Item = Result<(Index<T>, &'tx rkyv::Archived<T>), Error>> + use<'tx, 'env, TX, T, I, B> where
+		rkyv::Archived<T>: 'tx,
+	{
+		let (lo, lo_overflow) = match range.start_bound() {
+			Bound::Included(&x) => (u64::from(x), false),
+			Bound::Excluded(&x) => u64::from(x).overflowing_add(1),
+			Bound::Unbounded => (u64::MIN, false),
+		};
+		let (hi, hi_overflow) = match range.end_bound() {
+			Bound::Included(&x) => (u64::from(x), false),
+			Bound::Excluded(&x) => u64::from(x).overflowing_sub(1),
+			Bound::Unbounded => (u64::MAX, false),
+		};
+		Range::<TX, T> {
+			front: lmdb::Cursor::open(self.tx, self.dbi)?,
+			back: lmdb::Cursor::open(self.tx, self.dbi)?,
+			front_started: false,
+			back_started: false,
+			lo,
+			hi,
+			done: lo_overflow || hi_overflow || lo > hi,
+			_pd: PhantomData,
+		}
+	}
+
 	#[expect(clippy::iter_not_returning_iterator)]
 	#[throws]
-	pub fn iter(&self) -> impl Iterator<Item = (Index<T>, &'tx rkyv::Archived<T>)> + use<'tx, 'env, TX, T> where
-		rkyv::Archived<T>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<(Index<T>, &'tx rkyv::Archived<T>), Error>> + use<'tx, 'env, TX, T, I> where
+		rkyv::Archived<T>: 'tx,
 	{
-		struct Cursor<'tx, TX, T>(lmdb::Cursor<'tx, TX>, PhantomData<T>);
-
-		impl<'tx, 'env: 'tx, TX, T> Iterator for Cursor<'tx, TX, T> where
-			TX: Transaction<'env>,
-			T: rkyv::Archive,
-			rkyv::Archived<T>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
-		{
-			type Item = (Index<T>, &'tx rkyv::Archived<T>);
-
-			fn next(&mut self) -> Option<(Index<T>, &'tx rkyv::Archived<T>)> {
-				let (key_u64, value_bytes) = self.0.get_with_u64_key(lmdb::CursorOpFlags::Next)?;
-				let key = Index::from(key_u64);
-				let value = match rkyv::access::<rkyv::Archived<T>, _>(value_bytes) {
-					Ok(x) => x,
-					Err(e) => { log::error!("Error deserializing value in rev cursor: {e:?}"); return None; }
-				};
-				Some((key, value))
+		self.range(..)?
+	}
+}
+
+// separate cursors for both ends, bounds are inclusive and shrink as either end yields
+// stops after the first error
+struct Range<'tx, TX, T> {
+	front: lmdb::Cursor<'tx, TX>,
+	back: lmdb::Cursor<'tx, TX>,
+	front_started: bool,
+	back_started: bool,
+	lo: u64,
+	hi: u64,
+	done: bool,
+	_pd: PhantomData<T>,
+}
+
+impl<'tx, 'env: 'tx, TX, T> Range<'tx, TX, T> where
+	TX: Transaction<'env>,
+	T: rkyv::Archive,
+	rkyv::Archived<T>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	fn yielded(&mut self, get: Result<Option<(u64, &'tx [u8])>, lmdb::Error>) -> Option<<Self as Iterator>::Item> {
+		let (key_u64, value_bytes) = match get {
+			Ok(Some(x)) => x,
+			Ok(None) => { self.done = true; return None; },
+			Err(e) => { self.done = true; return Some(Err(e.into())); },
+		};
+		if key_u64 < self.lo || key_u64 > self.hi { self.done = true; return None; }
+		match rkyv::access::<rkyv::Archived<T>, _>(value_bytes) {
+			Ok(value) => Some(Ok((Index::from(key_u64), value))),
+			Err(e) => { self.done = true; Some(Err(e.into())) },
+		}
+	}
+}
+
+impl<'tx, 'env: 'tx, TX, T> Iterator for Range<'tx, TX, T> where
+	TX: Transaction<'env>,
+	T: rkyv::Archive,
+	rkyv::Archived<T>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	type Item = Result<(Index<T>, &'tx rkyv::Archived<T>), Error>;
+
+	fn next(&mut self) -> Option<Result<(Index<T>, &'tx rkyv::Archived<T>), Error>> {
+		if self.done { return None; }
+		let get = if self.front_started {
+			self.front.get_with_u64_key(lmdb::CursorOpFlags::Next)
+		} else {
+			self.front_started = true;
+			self.front.get_with_u64_key_in(self.lo, lmdb::CursorOpFlags::SetRange)
+		};
+		let item = self.yielded(get)?;
+		if let Ok((index, _)) = item {
+			match u64::from(index).checked_add(1) {
+				Some(lo) => self.lo = lo,
+				None => self.done = true,
+			}
+		}
+		Some(item)
+	}
+}
+
+impl<'tx, 'env: 'tx, TX, T> DoubleEndedIterator for Range<'tx, TX, T> where
+	TX: Transaction<'env>,
+	T: rkyv::Archive,
+	rkyv::Archived<T>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	fn next_back(&mut self) -> Option<Result<(Index<T>, &'tx rkyv::Archived<T>), Error>> {
+		if self.done { return None; }
+		let get = if self.back_started {
+			self.back.get_with_u64_key(lmdb::CursorOpFlags::Prev)
+		} else {
+			self.back_started = true;
+			// SetRange finds the first key >= hi, which is one too far unless it's hi itself
+			match self.back.get_with_u64_key_in(self.hi, lmdb::CursorOpFlags::SetRange) {
+				Ok(Some((key_u64, value_bytes))) if key_u64 == self.hi => Ok(Some((key_u64, value_bytes))),
+				Ok(Some(_)) => self.back.get_with_u64_key(lmdb::CursorOpFlags::Prev),
+				Ok(None) => self.back.get_with_u64_key(lmdb::CursorOpFlags::Last),
+				Err(e) => Err(e),
+			}
+		};
+		let item = self.yielded(get)?;
+		if let Ok((index, _)) = item {
+			match u64::from(index).checked_sub(1) {
+				Some(hi) => self.hi = hi,
+				None => self.done = true,
 			}
 		}
+		Some(item)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, IndexTable, Index, Error, Transaction, lmdb, test_env};
+	use std::ops::Bound;
+
+	#[derive(DbName)]
+	#[table(IndexTable<'tx, TX, u32>)]
+	struct Numbers;
+
+	fn ix(x: u64) -> Index<u32> { Index::from(x) }
+
+	fn env(indices: &[u64]) -> test_env::TestEnv {
+		let env = test_env::build(|b| b.with::<Numbers>());
+		env.write_blocking(|tx| {
+			for &index in indices { Numbers::get(tx).put(ix(index), &(index as u32))?; }
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		env
+	}
+
+	fn keys<'a>(iter: impl Iterator<Item = Result<(Index<u32>, &'a rkyv::Archived<u32>), Error>>) -> Vec<u64> {
+		iter.map(|get| { let (index, value) = get.unwrap(); assert_eq!(value.to_native(), u64::from(index) as u32); u64::from(index) }).collect()
+	}
+
+	#[test]
+	fn bounds() {
+		let env = env(&[0, 2, 4, 6, 8]);
+		let tx = env.read_tx().unwrap();
+		let numbers = Numbers::get(&tx);
+		assert_eq!(keys(numbers.range(..).unwrap()), [0, 2, 4, 6, 8]);
+		assert_eq!(keys(numbers.range(ix(2)..ix(6)).unwrap()), [2, 4]);
+		assert_eq!(keys(numbers.range(ix(2)..=ix(6)).unwrap()), [2, 4, 6]);
+		assert_eq!(keys(numbers.range(ix(3)..ix(7)).unwrap()), [4, 6]);
+		assert_eq!(keys(numbers.range((Bound::Excluded(ix(2)), Bound::Included(ix(6)))).unwrap()), [4, 6]);
+		assert_eq!(keys(numbers.range((Bound::Excluded(ix(2)), Bound::Excluded(ix(8)))).unwrap()), [4, 6]);
+		assert_eq!(keys(numbers.range(..=ix(0)).unwrap()), [0]);
+		assert!(keys(numbers.range(..ix(0)).unwrap()).is_empty());
+		assert!(keys(numbers.range(ix(9)..).unwrap()).is_empty());
+		assert_eq!(keys(numbers.range(ix(2)..=ix(6)).unwrap().rev()), [6, 4, 2]);
+		assert_eq!(keys(numbers.range(ix(1)..ix(7)).unwrap().rev()), [6, 4, 2]);
+		assert_eq!(numbers.first().unwrap().map(|(index, _)| u64::from(index)), Some(0));
+		assert_eq!(numbers.last().unwrap().map(|(index, _)| u64::from(index)), Some(8));
+	}
+
+	#[test]
+	fn empty() {
+		let env = env(&[]);
+		let tx = env.read_tx().unwrap();
+		let numbers = Numbers::get(&tx);
+		assert!(numbers.first().unwrap().is_none());
+		assert!(numbers.last().unwrap().is_none());
+		assert!(numbers.iter().unwrap().next().is_none());
+		assert!(numbers.iter().unwrap().next_back().is_none());
+		assert!(numbers.range(ix(1)..=ix(5)).unwrap().next().is_none());
+	}
+
+	#[test]
+	fn ends_at_max() {
+		let env = env(&[0, u64::MAX - 1, u64::MAX]);
+		let tx = env.read_tx().unwrap();
+		let numbers = Numbers::get(&tx);
+		assert_eq!(keys(numbers.range(ix(u64::MAX - 1)..).unwrap()), [u64::MAX - 1, u64::MAX]);
+		assert_eq!(keys(numbers.range(..=ix(u64::MAX)).unwrap()), [0, u64::MAX - 1, u64::MAX]);
+		assert_eq!(keys(numbers.range(..ix(u64::MAX)).unwrap()), [0, u64::MAX - 1]);
+		assert_eq!(keys(numbers.iter().unwrap().rev()), [u64::MAX, u64::MAX - 1, 0]);
+		assert_eq!(keys(numbers.range((Bound::Excluded(ix(u64::MAX - 1)), Bound::Unbounded)).unwrap()), [u64::MAX]);
+		assert!(keys(numbers.range((Bound::Excluded(ix(u64::MAX)), Bound::Unbounded)).unwrap()).is_empty());
+
+		let mut iter = numbers.range(ix(u64::MAX - 1)..).unwrap();
+		assert_eq!(iter.next_back().map(|get| u64::from(get.unwrap().0)), Some(u64::MAX));
+		assert_eq!(iter.next().map(|get| u64::from(get.unwrap().0)), Some(u64::MAX - 1));
+		assert!(iter.next().is_none());
+		assert!(iter.next_back().is_none());
+	}
 
-		Cursor::<TX, T>(lmdb::Cursor::open(self.tx, self.dbi)?, PhantomData)
+	#[test]
+	fn short_key_is_an_error() {
+		let env = test_env::build(|b| b.with::<Numbers>());
+		env.write_blocking(|tx| {
+			let dbi = tx.env().db(Numbers::NAME).unwrap();
+			lmdb::put(tx, dbi, &mut 1u32.to_ne_bytes(), &mut rkyv::to_bytes::<rkyv::rancor::Error>(&1u32)?)?;
+			let numbers = Numbers::get(tx);
+			assert!(matches!(numbers.first(), Err(Error::Lmdb(lmdb::Error::NotU64(4)))));
+			let mut iter = numbers.iter()?;
+			assert!(matches!(iter.next(), Some(Err(Error::Lmdb(lmdb::Error::NotU64(4))))));
+			assert!(iter.next().is_none());
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
 	}
 }
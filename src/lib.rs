@@ -15,9 +15,9 @@
 
 pub use batadase_index::Index;
 pub use batadase_macros::DbName;
-pub use env::Env;
+pub use env::{Env, ReadOnlyEnv, SharedEnv, SharedReadOnlyEnv, EnvInfo};
 pub use lmdb::{DbFlags, CursorOpFlags};
-pub use transaction::{Transaction, RoTxn, RwTxn};
+pub use transaction::{Transaction, RoTxn, ParkedRoTxn, RwTxn};
 pub use enumflags2;
 pub use error::Error;
 pub use rkyv;
@@ -31,15 +31,29 @@ pub mod index_table;
 pub mod assoc_table;
 pub mod index_poly_table;
 pub mod assoc_poly_table;
+pub mod multi_assoc_table;
+pub mod secondary_index;
+pub mod one_to_many_table;
+pub mod many_to_many_table;
+pub mod one_to_one_table;
+#[cfg(test)]
+mod test_env;
 pub use assoc_table::AssocTable;
 pub use index_poly_table::IndexPolyTable;
 pub use index_table::IndexTable;
 pub use assoc_poly_table::AssocPolyTable;
+pub use multi_assoc_table::MultiAssocTable;
+pub use secondary_index::SecondaryIndex;
+pub use one_to_many_table::OneToManyTable;
+pub use many_to_many_table::ManyToManyTable;
+pub use one_to_one_table::OneToOneTable;
 
 pub trait Table<'tx, 'env: 'tx, TX: Transaction<'env>> {
 	fn dbi(&self) -> lmdb_sys::MDB_dbi;
 	fn txn(&self) -> &TX;
 	fn flags() -> enumflags2::BitFlags<DbFlags> { enumflags2::BitFlags::empty() }
+	/// other dbs the table named `name` needs, registered along with it by `EnvBuilder::with`
+	fn extra_dbs(_name: &'static [u8]) -> Vec<(std::borrow::Cow<'static, [u8]>, enumflags2::BitFlags<DbFlags>)> { Vec::new() }
 
 	#[culpa::throws]
 	fn entries(&self) -> usize {
@@ -47,13 +61,48 @@ pub trait Table<'tx, 'env: 'tx, TX: Transaction<'env>> {
 		stat.ms_entries
 	}
 
+	#[culpa::throws]
+	fn stats(&self) -> TableStats {
+		lmdb::stat(self.txn().raw(), self.dbi())?.into()
+	}
+
 	fn build(tx: &'tx TX, name: &'static [u8]) -> Self;
 }
 
+/// b-tree stats of a table, or of the whole env from [`Env::stat`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableStats {
+	pub page_size: u32,
+	pub depth: u32,
+	pub branch_pages: usize,
+	pub leaf_pages: usize,
+	pub overflow_pages: usize,
+	pub entries: usize,
+}
+
+impl From<lmdb_sys::MDB_stat> for TableStats {
+	fn from(stat: lmdb_sys::MDB_stat) -> Self {
+		Self {
+			page_size: stat.ms_psize,
+			depth: stat.ms_depth,
+			branch_pages: stat.ms_branch_pages,
+			leaf_pages: stat.ms_leaf_pages,
+			overflow_pages: stat.ms_overflow_pages,
+			entries: stat.ms_entries,
+		}
+	}
+}
+
+/// name of a db that belongs to the table named `name`, e.g. the reverse side of a relation
+pub fn sub_db_name(name: &[u8], suffix: &str) -> Vec<u8> {
+	let name = name.strip_suffix(b"\0").unwrap_or(name);
+	[name, b"::", suffix.as_bytes(), b"\0"].concat()
+}
+
 // potentially useful relation table flavours:
-// * one to many via Indices
-// * two-way one-to-one via Indices
-// * two-way many-to-many via Indices
+// * one to many via Indices - OneToManyTable
+// * two-way one-to-one via Indices - OneToOneTable
+// * two-way many-to-many via Indices - ManyToManyTable
 
 type RkyvSer<'a> = rkyv::api::high::HighSerializer<rkyv::util::AlignedVec, rkyv::ser::allocator::ArenaHandle<'a>, rkyv::rancor::Error>;
 type RkyvDe = rkyv::api::high::HighDeserializer<rkyv::rancor::Error>;
@@ -107,16 +156,16 @@ pub fn unrkyv_from_bytes<T>(bytes: &[u8]) -> Result<T, rkyv::rancor::Error> wher
 macro_rules! def_tx_ops {
 	// name of your &'static Env
 	($env_name:ident) => {
-		pub fn read_tx() -> ::std::result::Result<::batadase::transaction::RoTxn, ::batadase::Error>  { $env_name.read_tx() }
+		pub fn read_tx() -> ::std::result::Result<::batadase::transaction::RoTxn<'static>, ::batadase::Error>  { $env_name.read_tx() }
 
 		pub async fn write<Res, Job>(job: Job) -> ::std::result::Result<Res, ::batadase::Error> where
 			Res: ::std::marker::Send + 'static,
-			Job: (::std::ops::FnOnce(&::batadase::transaction::RwTxn) -> Res) + ::std::marker::Send + 'static,
+			Job: (::std::ops::FnMut(&::batadase::transaction::RwTxn) -> Res) + ::std::marker::Send + 'static,
 		{ $env_name.write(job).await }
 
 		pub async fn try_write<Res, Err, Job>(job: Job) -> ::std::result::Result<::std::result::Result<Res, Err>, ::batadase::Error> where
 			Res: ::std::marker::Send + 'static,
-			Job: (::std::ops::FnOnce(&::batadase::transaction::RwTxn) -> ::std::result::Result<Res, Err>) + ::std::marker::Send + 'static,
+			Job: (::std::ops::FnMut(&::batadase::transaction::RwTxn) -> ::std::result::Result<Res, Err>) + ::std::marker::Send + 'static,
 			Err: ::std::marker::Send + 'static,
 		{ $env_name.try_write(job).await }
 
@@ -125,19 +174,25 @@ macro_rules! def_tx_ops {
 
 		pub async fn try_write_async<Res, Error>(job: impl for <'tx> ::batadase::env::WriteCallback<'tx, ::std::result::Result<Res, Error>>) -> ::std::result::Result<::std::result::Result<Res, Error>, ::batadase::Error>
 		{ $env_name.try_write_async(job).await }
+
+		pub fn write_blocking<Res>(job: impl ::std::ops::FnMut(&::batadase::transaction::RwTxn) -> Res) -> ::std::result::Result<Res, ::batadase::Error>
+		{ $env_name.write_blocking(job) }
+
+		pub fn try_write_blocking<Res, Err>(job: impl ::std::ops::FnMut(&::batadase::transaction::RwTxn) -> ::std::result::Result<Res, Err>) -> ::std::result::Result<::std::result::Result<Res, Err>, ::batadase::Error>
+		{ $env_name.try_write_blocking(job) }
 	};
 
 	($env_name:ident, $err:ty) => {
-		pub fn read_tx() -> ::std::result::Result<::batadase::transaction::RoTxn, ::batadase::Error>  { $env_name.read_tx() }
+		pub fn read_tx() -> ::std::result::Result<::batadase::transaction::RoTxn<'static>, ::batadase::Error>  { $env_name.read_tx() }
 
 		pub async fn write<Res, Job>(job: Job) -> ::std::result::Result<Res, ::batadase::Error> where
 			Res: ::std::marker::Send + 'static,
-			Job: (::std::ops::FnOnce(&::batadase::transaction::RwTxn) -> Res) + ::std::marker::Send + 'static,
+			Job: (::std::ops::FnMut(&::batadase::transaction::RwTxn) -> Res) + ::std::marker::Send + 'static,
 		{ $env_name.write(job).await }
 
 		pub async fn try_write<Res, Job>(job: Job) -> ::std::result::Result<::std::result::Result<Res, $err>, ::batadase::Error> where
 			Res: ::std::marker::Send + 'static,
-			Job: (::std::ops::FnOnce(&::batadase::transaction::RwTxn) -> ::std::result::Result<Res, $err>) + ::std::marker::Send + 'static,
+			Job: (::std::ops::FnMut(&::batadase::transaction::RwTxn) -> ::std::result::Result<Res, $err>) + ::std::marker::Send + 'static,
 		{ $env_name.try_write(job).await }
 
 		pub async fn write_async<Res>(job: impl for <'tx> ::batadase::env::WriteCallback<'tx, Res>) -> ::std::result::Result<Res, ::batadase::Error>
@@ -145,6 +200,12 @@ macro_rules! def_tx_ops {
 
 		pub async fn try_write_async<Res>(job: impl for <'tx> ::batadase::env::WriteCallback<'tx, ::std::result::Result<Res, $err>>) -> ::std::result::Result<::std::result::Result<Res, $err>, ::batadase::Error>
 		{ $env_name.try_write_async(job).await }
+
+		pub fn write_blocking<Res>(job: impl ::std::ops::FnMut(&::batadase::transaction::RwTxn) -> Res) -> ::std::result::Result<Res, ::batadase::Error>
+		{ $env_name.write_blocking(job) }
+
+		pub fn try_write_blocking<Res, Err>(job: impl ::std::ops::FnMut(&::batadase::transaction::RwTxn) -> ::std::result::Result<Res, Err>) -> ::std::result::Result<::std::result::Result<Res, Err>, ::batadase::Error>
+		{ $env_name.try_write_blocking(job) }
 	};
 
 	// the only error most people should really care about is read_tx's ReadersFull
@@ -153,12 +214,12 @@ macro_rules! def_tx_ops {
 
 		pub async fn write<Res, Job>(job: Job) -> Res where
 			Res: ::std::marker::Send + 'static,
-			Job: (::std::ops::FnOnce(&::batadase::transaction::RwTxn) -> Res) + ::std::marker::Send + 'static,
+			Job: (::std::ops::FnMut(&::batadase::transaction::RwTxn) -> Res) + ::std::marker::Send + 'static,
 		{ $env_name.write(job).await.unwrap() }
 
 		pub async fn try_write<Res, Job>(job: Job) -> ::std::result::Result<Res, $err> where
 			Res: ::std::marker::Send + 'static,
-			Job: (::std::ops::FnOnce(&::batadase::transaction::RwTxn) -> ::std::result::Result<Res, $err>) + ::std::marker::Send + 'static,
+			Job: (::std::ops::FnMut(&::batadase::transaction::RwTxn) -> ::std::result::Result<Res, $err>) + ::std::marker::Send + 'static,
 		{ $env_name.try_write(job).await.unwrap() }
 
 		pub async fn write_async<Res>(job: impl for <'tx> ::batadase::env::WriteCallback<'tx, Res>) -> Res
@@ -166,5 +227,11 @@ macro_rules! def_tx_ops {
 
 		pub async fn try_write_async<Res>(job: impl for <'tx> ::batadase::env::WriteCallback<'tx, ::std::result::Result<Res, $err>>) -> ::std::result::Result<Res, $err>
 		{ $env_name.try_write_async(job).await.unwrap() }
+
+		pub fn write_blocking<Res>(job: impl ::std::ops::FnMut(&::batadase::transaction::RwTxn) -> Res) -> Res
+		{ $env_name.write_blocking(job).unwrap() }
+
+		pub fn try_write_blocking<Res>(job: impl ::std::ops::FnMut(&::batadase::transaction::RwTxn) -> ::std::result::Result<Res, $err>) -> ::std::result::Result<Res, $err>
+		{ $env_name.try_write_blocking(job).unwrap() }
 	};
 }
@@ -94,10 +94,11 @@ impl<'tx, 'env: 'tx, TX> Cursor<'tx, TX> where
 		Self(cursor, tx)
 	}
 
+	#[throws]
 	pub(super) fn get(&mut self, flags: CursorOpFlags) -> Option<(&'tx [u8], &'tx [u8])> {
 		let mut key = Val::new_outparam(self.1);
 		let mut value = Val::new_outparam(self.1);
-		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) }) { return None }
+		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) })? { return None }
 		Some((
 			key.as_slice(),
 			value.as_slice(),
@@ -105,25 +106,65 @@ impl<'tx, 'env: 'tx, TX> Cursor<'tx, TX> where
 	}
 
 	// flags must not include CursorOpFlags::Set because that doesn't change key
+	#[throws]
 	pub(super) fn get_with_key(&mut self, key_in: &mut [u8], flags: CursorOpFlags) -> Option<(&'tx [u8], &'tx [u8])> {
 		let mut key = Val::new_outparam(self.1);
 		key.mv_size = key_in.len();
 		key.mv_data = key_in.as_mut_ptr().cast();
 		let mut value = Val::new_outparam(self.1);
-		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) }) { return None }
+		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) })? { return None }
 		Some((
 			key.as_slice(),
 			value.as_slice(),
 		))
 	}
 
+	// for DbFlags::DupSort tables, all values of the key
+	// stops after the first error
+	#[throws]
+	pub(super) fn into_dups(mut self, key_in: &mut [u8]) -> impl Iterator<Item = Result<(&'tx [u8], &'tx [u8]), Error>> + use<'tx, 'env, TX> {
+		let first = self.get_with_key(key_in, CursorOpFlags::SetKey)?;
+		// NextDup on a cursor that isn't positioned at the key moves to an arbitrary one
+		let mut done = first.is_none();
+		first.map(Ok).into_iter().chain(std::iter::from_fn(move || {
+			if done { return None; }
+			let res = self.get(CursorOpFlags::NextDup).transpose();
+			done = !matches!(res, Some(Ok(_)));
+			res
+		}))
+	}
+
+	// for DbFlags::DupSort tables, positions at the exact key/value pair
+	#[throws]
+	pub(super) fn get_both(&mut self, key_in: &mut [u8], value_in: &mut [u8]) -> bool {
+		let mut key = Val::from_buf(key_in);
+		let mut value = Val::from_buf(value_in);
+		error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, CursorOpFlags::GetBoth as _) })?
+	}
+
+	// for DbFlags::DupSort tables, number of values for the key at the current position
+	#[throws]
+	pub(super) fn count(&self) -> usize {
+		let mut count = 0;
+		error::handle_cursor_count_code(unsafe { sys::mdb_cursor_count(self.0, &mut count) })?;
+		count
+	}
+
+	// flags must not include CursorOpFlags::Set because that doesn't change key
+	#[throws]
+	pub(super) fn get_with_u64_key_in(&mut self, key_in: u64, flags: CursorOpFlags) -> Option<(u64, &'tx [u8])> {
+		let mut key_bytes = key_in.to_ne_bytes();
+		let Some((key, value)) = self.get_with_key(&mut key_bytes, flags)? else { return None; };
+		Some((u64_from_bytes(key)?, value))
+	}
+
+	#[throws]
 	pub(super) fn get_with_u64_key(&mut self, flags: CursorOpFlags) -> Option<(u64, &'tx [u8])> {
 		let mut key = Val::new_outparam(self.1);
 		let mut value = Val::new_outparam(self.1);
-		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) }) { return None }
-		debug_assert!(key.mv_size == std::mem::size_of::<u64>());
+		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) })? { return None }
 		Some((
-			u64::from_ne_bytes(unsafe { *key.mv_data.cast::<[u8; std::mem::size_of::<u64>()]>() }),
+			u64_from_bytes(key.as_slice())?,
 			value.as_slice(),
 		))
 	}
@@ -137,17 +178,37 @@ impl<TX> Drop for Cursor<'_, TX> {
 
 #[throws]
 pub(super) fn put(tx: &RwTxn, dbi: sys::MDB_dbi, key: impl AsMut<[u8]>, val: impl AsMut<[u8]>) {
-	error::handle_put_code(unsafe { sys::mdb_put(tx.raw(), dbi, &mut *Val::from_buf(key), &mut *Val::from_buf(val), 0) })?;
+	tx.note_map_full(error::handle_put_code(unsafe { sys::mdb_put(tx.raw(), dbi, &mut *Val::from_buf(key), &mut *Val::from_buf(val), 0) }))?;
+}
+
+// MDB_NOOVERWRITE, returns the existing value instead of overwriting it
+#[throws]
+pub(super) fn put_no_overwrite<'tx>(tx: &'tx RwTxn, dbi: sys::MDB_dbi, key: impl AsMut<[u8]>, mut val: impl AsMut<[u8]>) -> Option<&'tx [u8]> {
+	let val_in = val.as_mut();
+	let mut value = Val::new_outparam(tx);
+	value.mv_size = val_in.len();
+	value.mv_data = val_in.as_mut_ptr().cast();
+	match unsafe { sys::mdb_put(tx.raw(), dbi, &mut *Val::from_buf(key), &mut *value, sys::MDB_NOOVERWRITE) } {
+		sys::MDB_KEYEXIST => return Some(value.as_slice()),
+		code => tx.note_map_full(error::handle_put_code(code))?,
+	}
+	None
 }
 
 #[throws]
 pub(super) fn del(tx: &RwTxn, dbi: sys::MDB_dbi, key: impl AsMut<[u8]>) -> bool {
-	error::handle_del_code(unsafe { sys::mdb_del(tx.raw(), dbi, &mut *Val::from_buf(key), std::ptr::null_mut()) })?
+	tx.note_map_full(error::handle_del_code(unsafe { sys::mdb_del(tx.raw(), dbi, &mut *Val::from_buf(key), std::ptr::null_mut()) }))?
+}
+
+// for DbFlags::DupSort tables, deletes just the one key/value pair
+#[throws]
+pub(super) fn del_pair(tx: &RwTxn, dbi: sys::MDB_dbi, key: impl AsMut<[u8]>, val: impl AsMut<[u8]>) -> bool {
+	tx.note_map_full(error::handle_del_code(unsafe { sys::mdb_del(tx.raw(), dbi, &mut *Val::from_buf(key), &mut *Val::from_buf(val)) }))?
 }
 
 #[throws]
 pub(super) fn drop(tx: &RwTxn, dbi: sys::MDB_dbi) {
-	error::handle_drop_code(unsafe { sys::mdb_drop(tx.raw(), dbi, 0) })?;
+	tx.note_map_full(error::handle_drop_code(unsafe { sys::mdb_drop(tx.raw(), dbi, 0) }))?;
 }
 
 #[throws]
@@ -157,13 +218,35 @@ pub(super) fn get<'tx, 'env: 'tx>(tx: &'tx impl Transaction<'env>, dbi: sys::MDB
 	Some(value.as_slice())
 }
 
+// compares keys the way the db orders them
+pub(super) fn cmp<'env>(tx: &impl Transaction<'env>, dbi: sys::MDB_dbi, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+	let a = sys::MDB_val { mv_size: a.len(), mv_data: a.as_ptr().cast_mut().cast() };
+	let b = sys::MDB_val { mv_size: b.len(), mv_data: b.as_ptr().cast_mut().cast() };
+	unsafe { sys::mdb_cmp(tx.raw(), dbi, &a, &b) }.cmp(&0)
+}
+
 #[throws]
-pub(super) fn txn_begin(env: *mut sys::MDB_env, flags: u32) -> *mut sys::MDB_txn {
+pub(super) fn txn_begin(env: *mut sys::MDB_env, parent: *mut sys::MDB_txn, flags: u32) -> *mut sys::MDB_txn {
 	let mut tx: *mut sys::MDB_txn = std::ptr::null_mut();
-	error::handle_txn_begin_code(unsafe { sys::mdb_txn_begin(env, std::ptr::null_mut(), flags, &mut tx) })?;
+	error::handle_txn_begin_code(unsafe { sys::mdb_txn_begin(env, parent, flags, &mut tx) })?;
 	tx
 }
 
+// releases a read tx's snapshot, keeping it for txn_renew
+pub(super) fn txn_reset(tx: *mut sys::MDB_txn) {
+	unsafe { sys::mdb_txn_reset(tx) }
+}
+
+#[throws]
+pub(super) fn txn_renew(tx: *mut sys::MDB_txn) {
+	error::handle_txn_begin_code(unsafe { sys::mdb_txn_renew(tx) })?;
+}
+
+// the id of the snapshot a read tx sees, or of a write tx
+pub(super) fn txn_id(tx: *mut sys::MDB_txn) -> usize {
+	unsafe { sys::mdb_txn_id(tx) }
+}
+
 #[throws]
 pub(super) fn txn_commit(tx: *mut sys::MDB_txn) {
 	error::handle_txn_commit_code(unsafe { sys::mdb_txn_commit(tx) })?;
@@ -186,16 +269,68 @@ pub(super) fn env_set_mapsize(env: *mut sys::MDB_env, mapsize: usize) {
 	error::handle_env_set_mapsize_code(unsafe { sys::mdb_env_set_mapsize(env, mapsize) })?;
 }
 
+#[throws]
+pub(super) fn env_info(env: *mut sys::MDB_env) -> sys::MDB_envinfo {
+	let mut info: sys::MDB_envinfo = unsafe { std::mem::zeroed() };
+	error::handle_env_info_code(unsafe { sys::mdb_env_info(env, &mut info) })?;
+	info
+}
+
+#[throws]
+pub(super) fn env_stat(env: *mut sys::MDB_env) -> sys::MDB_stat {
+	let mut stat: sys::MDB_stat = unsafe { std::mem::zeroed() };
+	error::handle_stat_code(unsafe { sys::mdb_env_stat(env, &mut stat) })?;
+	stat
+}
+
+#[throws]
+pub(super) fn env_copy(env: *mut sys::MDB_env, path: &std::ffi::CStr, flags: u32) {
+	error::handle_env_copy_code(unsafe { sys::mdb_env_copy2(env, path.as_ptr(), flags) })?;
+}
+
+#[throws]
+pub(super) fn env_copy_fd(env: *mut sys::MDB_env, fd: sys::mdb_filehandle_t, flags: u32) {
+	error::handle_env_copy_code(unsafe { sys::mdb_env_copyfd2(env, fd, flags) })?;
+}
+
+// returns how many stale reader slots were cleared
+#[throws]
+pub(super) fn reader_check(env: *mut sys::MDB_env) -> usize {
+	let mut dead: i32 = 0;
+	error::handle_reader_check_code(unsafe { sys::mdb_reader_check(env, &mut dead) })?;
+	dead as usize
+}
+
+// calls `line` with each line of the reader lock table
+#[throws]
+pub(super) fn reader_list(env: *mut sys::MDB_env, mut line: impl FnMut(&str)) {
+	unsafe extern "C" fn msg(msg: *const libc::c_char, ctx: *mut libc::c_void) -> i32 {
+		let line = &mut *ctx.cast::<&mut dyn FnMut(&str)>();
+		line(&std::ffi::CStr::from_ptr(msg).to_string_lossy());
+		0
+	}
+	let mut line: &mut dyn FnMut(&str) = &mut line;
+	error::handle_reader_list_code(unsafe { sys::mdb_reader_list(env, Some(msg), std::ptr::from_mut(&mut line).cast()) })?;
+}
+
+#[throws]
+pub(super) fn env_sync(env: *mut sys::MDB_env) {
+	error::handle_env_sync_code(unsafe { sys::mdb_env_sync(env, 1) })?;
+}
+
+// also fine for an env that was created but never opened
+pub(super) fn env_close(env: *mut sys::MDB_env) {
+	unsafe { sys::mdb_env_close(env) }
+}
+
 #[throws]
 pub(super) fn env_set_maxreaders(env: *mut sys::MDB_env, maxreaders: u32) {
 	error::handle_env_set_maxreaders_code(unsafe { sys::mdb_env_set_maxreaders(env, maxreaders) })?;
 }
 
-#[allow(unused_variables)]
 #[throws]
 pub(super) fn env_open(env: *mut sys::MDB_env, path: &std::ffi::CStr, flags: u32, mode: u32) {
-	#[cfg(unix)] let mode = mode;
-	#[cfg(windows)] let mode = 0;
+	#[cfg(windows)] let mode = { let _ = mode; 0 };
 	error::handle_env_open(unsafe { sys::mdb_env_open(env, path.as_ptr(), flags, mode) })?;
 }
 
@@ -205,6 +340,18 @@ pub(super) fn dbi_open(tx: *mut sys::MDB_txn, name: &[u8], flags: enumflags2::Bi
 	dbi
 }
 
+// without DbFlags::Create, so the db might not exist
+#[throws]
+pub(super) fn dbi_open_existing(tx: *mut sys::MDB_txn, name: &[u8], flags: enumflags2::BitFlags<DbFlags>) -> Option<sys::MDB_dbi> {
+	let mut dbi: sys::MDB_dbi = 0;
+	if !error::handle_dbi_open_existing_code(unsafe { sys::mdb_dbi_open(tx, name.as_ptr().cast(), flags.bits(), &mut dbi) })? { return None; }
+	Some(dbi)
+}
+
+pub(super) fn dbi_close(env: *mut sys::MDB_env, dbi: sys::MDB_dbi) {
+	unsafe { sys::mdb_dbi_close(env, dbi) }
+}
+
 #[throws]
 pub(super) fn stat(txn: *mut sys::MDB_txn, dbi: sys::MDB_dbi) -> sys::MDB_stat {
 	let mut stat: sys::MDB_stat = unsafe { std::mem::zeroed() };
@@ -212,6 +359,13 @@ pub(super) fn stat(txn: *mut sys::MDB_txn, dbi: sys::MDB_dbi) -> sys::MDB_stat {
 	stat
 }
 
+// keys and values of IntegerKey/IntegerDup Index dbs, which LMDB would also let be 4 bytes
+#[throws]
+pub(super) fn u64_from_bytes(bytes: &[u8]) -> u64 {
+	let Ok(bytes) = bytes.try_into() else { culpa::throw!(Error::NotU64(bytes.len())); };
+	u64::from_ne_bytes(bytes)
+}
+
 pub trait MdbValExt {
 	#[expect(clippy::missing_safety_doc)]
 	unsafe fn as_slice(&self) -> &[u8];
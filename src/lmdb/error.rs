@@ -1,6 +1,6 @@
 use culpa::{throw, throws};
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
 	#[error("the version of the LMDB library doesn't match the version that created the database environment")] VersionMismatch,
 	#[error("the environment file headers are corrupted")] Corrupted,
@@ -20,6 +20,9 @@ pub enum Error {
 	#[error("out of memory")] Oom,
 	#[error("key already exists and overwrite isn't requested")] KeyExists,
 	#[error("unsupported size of key/DB name/data, or wrong DUPFIXED size")] BadValSize,
+	#[error("too many named databases")] DbsFull,
+	#[error("the database was created with incompatible flags")] Incompatible,
+	#[error("expected an 8 byte integer key or value, found {0} bytes")] NotU64(usize),
 	#[error("misc error {0}")] Misc(i32),
 }
 
@@ -28,6 +31,7 @@ pub(crate) fn handle_del_code(code: i32) -> bool {
 	match code {
 		lmdb_sys::MDB_SUCCESS => true,
 		lmdb_sys::MDB_NOTFOUND => false,
+		lmdb_sys::MDB_MAP_FULL => throw!(Error::MapFull),
 		libc::EACCES => throw!(Error::TxnPerm),
 		libc::EINVAL => throw!(Error::InvalidParameter),
 		code => throw!(Error::Misc(code)),
@@ -52,6 +56,7 @@ pub(crate) fn handle_put_code(code: i32) {
 pub(crate) fn handle_drop_code(code: i32) {
 	match code {
 		lmdb_sys::MDB_SUCCESS => {},
+		lmdb_sys::MDB_MAP_FULL => throw!(Error::MapFull),
 		code => throw!(Error::Misc(code)),
 	}
 }
@@ -75,12 +80,22 @@ pub(crate) fn handle_cursor_open_code(code: i32) {
 	}
 }
 
+#[throws]
 pub(crate) fn handle_cursor_get_code(code: i32) -> bool {
 	match code {
 		lmdb_sys::MDB_SUCCESS => true,
 		lmdb_sys::MDB_NOTFOUND => false,
-		libc::EINVAL => panic!("cursor invalid parameter"),
-		e => panic!("cursor misc error {e}"),
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		code => throw!(Error::Misc(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_cursor_count_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		code => throw!(Error::Misc(code)),
 	}
 }
 
@@ -100,6 +115,7 @@ pub(crate) fn handle_txn_begin_code(code: i32) {
 pub(crate) fn handle_txn_commit_code(code: i32) {
 	match code {
 		lmdb_sys::MDB_SUCCESS => {},
+		lmdb_sys::MDB_MAP_FULL => culpa::throw!(Error::MapFull),
 		libc::EINVAL => culpa::throw!(Error::InvalidParameter),
 		libc::ENOSPC => culpa::throw!(Error::NoDiskSpace),
 		libc::EIO => culpa::throw!(Error::Io),
@@ -132,6 +148,57 @@ pub(crate) fn handle_env_set_mapsize_code(code: i32) {
 	}
 }
 
+#[throws]
+pub(crate) fn handle_env_info_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		code => culpa::throw!(Error::Misc(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_env_sync_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EACCES => culpa::throw!(Error::NoAccess),
+		libc::EINVAL => culpa::throw!(Error::InvalidParameter),
+		libc::EIO => culpa::throw!(Error::Io),
+		code => culpa::throw!(Error::Misc(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_env_copy_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::ENOENT => culpa::throw!(Error::DirDoesntExist),
+		libc::EACCES => culpa::throw!(Error::NoAccess),
+		libc::ENOSPC => culpa::throw!(Error::NoDiskSpace),
+		libc::EIO => culpa::throw!(Error::Io),
+		libc::ENOMEM => culpa::throw!(Error::Oom),
+		libc::EINVAL => culpa::throw!(Error::InvalidParameter),
+		code => culpa::throw!(Error::Misc(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_reader_check_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EINVAL => culpa::throw!(Error::InvalidParameter),
+		code => culpa::throw!(Error::Misc(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_reader_list_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EINVAL => culpa::throw!(Error::InvalidParameter),
+		code => culpa::throw!(Error::Misc(code)),
+	}
+}
+
 #[throws]
 pub(crate) fn handle_env_set_maxreaders_code(code: i32) {
 	match code {
@@ -162,6 +229,18 @@ pub(crate) fn handle_dbi_open_code(code: i32) {
 	}
 }
 
+#[throws]
+pub(crate) fn handle_dbi_open_existing_code(code: i32) -> bool {
+	match code {
+		lmdb_sys::MDB_SUCCESS => true,
+		lmdb_sys::MDB_NOTFOUND => false,
+		lmdb_sys::MDB_DBS_FULL => culpa::throw!(Error::DbsFull),
+		lmdb_sys::MDB_INCOMPATIBLE => culpa::throw!(Error::Incompatible),
+		libc::EINVAL => culpa::throw!(Error::InvalidParameter),
+		code => culpa::throw!(Error::Misc(code)),
+	}
+}
+
 #[throws]
 pub(crate) fn handle_stat_code(code: i32) {
 	match code {
@@ -0,0 +1,199 @@
+use crate::{Transaction, RwTxn, Table, Error, lmdb, DbFlags, sub_db_name};
+use culpa::throws;
+use batadase_index::Index;
+use std::marker::PhantomData;
+
+/// Left to rights is a DupSort db, right to lefts is a separate `::reverse` DupSort db.
+pub struct ManyToManyTable<'tx, TX, Left, Right> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	reverse_dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<(Left, Right)>,
+}
+
+impl<'tx, 'env: 'tx, TX, Left, Right> Table<'tx, 'env, TX> for ManyToManyTable<'tx, TX, Left, Right> where
+	TX: Transaction<'env>,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn flags() -> enumflags2::BitFlags<DbFlags> { DbFlags::IntegerKey | DbFlags::DupSort | DbFlags::DupFixed | DbFlags::IntegerDup }
+	fn extra_dbs(name: &'static [u8]) -> Vec<(std::borrow::Cow<'static, [u8]>, enumflags2::BitFlags<DbFlags>)> {
+		vec![(sub_db_name(name, "reverse").into(), Self::flags())]
+	}
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).unwrap(), tx.env().db(&sub_db_name(name, "reverse")).unwrap())
+	}
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, Left, Right> ManyToManyTable<'tx, RwTxn<'tx>, Left, Right> {
+	/// linking an already linked pair is a no-op
+	#[throws]
+	pub fn link(&self, left: Index<Left>, right: Index<Right>) {
+		let mut left_bytes = u64::from(left).to_ne_bytes();
+		let mut right_bytes = u64::from(right).to_ne_bytes();
+		lmdb::put(self.tx, self.dbi, &mut left_bytes, &mut right_bytes)?;
+		lmdb::put(self.tx, self.reverse_dbi, &mut right_bytes, &mut left_bytes)?;
+	}
+
+	#[throws]
+	pub fn unlink(&self, left: Index<Left>, right: Index<Right>) -> bool {
+		let mut left_bytes = u64::from(left).to_ne_bytes();
+		let mut right_bytes = u64::from(right).to_ne_bytes();
+		if !lmdb::del_pair(self.tx, self.dbi, &mut left_bytes, &mut right_bytes)? { return false; }
+		lmdb::del_pair(self.tx, self.reverse_dbi, &mut right_bytes, &mut left_bytes)?
+	}
+
+	/// unlinks the left from all its rights, returns how many were unlinked
+	#[throws]
+	pub fn unlink_left(&self, left: Index<Left>) -> usize {
+		let mut left_bytes = u64::from(left).to_ne_bytes();
+		let rights = self.rights_of(left)?.collect::<Result<Vec<_>, _>>()?;
+		for &right in &rights {
+			let mut right_bytes = u64::from(right).to_ne_bytes();
+			lmdb::del_pair(self.tx, self.reverse_dbi, &mut right_bytes, &mut left_bytes)?;
+		}
+		lmdb::del(self.tx, self.dbi, &mut left_bytes)?;
+		rights.len()
+	}
+
+	/// unlinks the right from all its lefts, returns how many were unlinked
+	#[throws]
+	pub fn unlink_right(&self, right: Index<Right>) -> usize {
+		let mut right_bytes = u64::from(right).to_ne_bytes();
+		let lefts = self.lefts_of(right)?.collect::<Result<Vec<_>, _>>()?;
+		for &left in &lefts {
+			let mut left_bytes = u64::from(left).to_ne_bytes();
+			lmdb::del_pair(self.tx, self.dbi, &mut left_bytes, &mut right_bytes)?;
+		}
+		lmdb::del(self.tx, self.reverse_dbi, &mut right_bytes)?;
+		lefts.len()
+	}
+
+	#[throws]
+	pub fn clear(&self) {
+		lmdb::drop(self.tx, self.dbi)?;
+		lmdb::drop(self.tx, self.reverse_dbi)?;
+	}
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, Left, Right> ManyToManyTable<'tx, TX, Left, Right> where
+	TX: Transaction<'env>,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi, reverse_dbi: lmdb_sys::MDB_dbi) -> Self {
+		Self { tx, dbi, reverse_dbi, _pd: PhantomData }
+	}
+
+	/// stops after the first error
+	#[throws]
+	pub fn rights_of(&self, left: Index<Left>) -> impl Iterator<Item = Result<Index<Right>, Error>> + use<'tx, 'env, TX, Left, Right> {
+		let mut key_bytes = u64::from(left).to_ne_bytes();
+		lmdb::Cursor::open(self.tx, self.dbi)?
+			.into_dups(&mut key_bytes)?
+			.map(|get| Ok(Index::from(lmdb::u64_from_bytes(get?.1)?)))
+	}
+
+	/// stops after the first error
+	#[throws]
+	pub fn lefts_of(&self, right: Index<Right>) -> impl Iterator<Item = Result<Index<Left>, Error>> + use<'tx, 'env, TX, Left, Right> {
+		let mut key_bytes = u64::from(right).to_ne_bytes();
+		lmdb::Cursor::open(self.tx, self.reverse_dbi)?
+			.into_dups(&mut key_bytes)?
+			.map(|get| Ok(Index::from(lmdb::u64_from_bytes(get?.1)?)))
+	}
+
+	#[throws]
+	pub fn contains(&self, left: Index<Left>, right: Index<Right>) -> bool {
+		let mut left_bytes = u64::from(left).to_ne_bytes();
+		let mut right_bytes = u64::from(right).to_ne_bytes();
+		lmdb::Cursor::open(self.tx, self.dbi)?.get_both(&mut left_bytes, &mut right_bytes)?
+	}
+
+	#[throws]
+	pub fn count_rights(&self, left: Index<Left>) -> usize {
+		let mut key_bytes = u64::from(left).to_ne_bytes();
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
+		if cursor.get_with_key(&mut key_bytes, lmdb::CursorOpFlags::SetKey)?.is_none() { return 0; }
+		cursor.count()?
+	}
+
+	#[throws]
+	pub fn count_lefts(&self, right: Index<Right>) -> usize {
+		let mut key_bytes = u64::from(right).to_ne_bytes();
+		let mut cursor = lmdb::Cursor::open(self.tx, self.reverse_dbi)?;
+		if cursor.get_with_key(&mut key_bytes, lmdb::CursorOpFlags::SetKey)?.is_none() { return 0; }
+		cursor.count()?
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, ManyToManyTable, Index, Error, test_env};
+
+	struct User;
+	struct Group;
+
+	#[derive(DbName)]
+	#[table(ManyToManyTable<'tx, TX, User, Group>)]
+	struct Members;
+
+	fn rights_of(env: &test_env::TestEnv, left: u64) -> Vec<u64> {
+		let tx = env.read_tx().unwrap();
+		Members::get(&tx).rights_of(Index::from(left)).unwrap().map(|right| u64::from(right.unwrap())).collect()
+	}
+
+	fn lefts_of(env: &test_env::TestEnv, right: u64) -> Vec<u64> {
+		let tx = env.read_tx().unwrap();
+		Members::get(&tx).lefts_of(Index::from(right)).unwrap().map(|left| u64::from(left.unwrap())).collect()
+	}
+
+	fn link(env: &test_env::TestEnv, links: &[(u64, u64)]) {
+		env.write_blocking(|tx| {
+			for &(left, right) in links { Members::get(tx).link(Index::from(left), Index::from(right))?; }
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+	}
+
+	#[test]
+	fn links_both_ways() {
+		let env = test_env::build(|b| b.with::<Members>());
+		link(&env, &[(1, 10), (1, 11), (2, 10)]);
+		assert_eq!(rights_of(&env, 1), [10, 11]);
+		assert_eq!(rights_of(&env, 2), [10]);
+		assert_eq!(lefts_of(&env, 10), [1, 2]);
+		assert_eq!(lefts_of(&env, 11), [1]);
+		let tx = env.read_tx().unwrap();
+		let members = Members::get(&tx);
+		assert!(members.contains(Index::from(2u64), Index::from(10u64)).unwrap());
+		assert!(!members.contains(Index::from(2u64), Index::from(11u64)).unwrap());
+		assert_eq!(members.count_rights(Index::from(1u64)).unwrap(), 2);
+		assert_eq!(members.count_lefts(Index::from(10u64)).unwrap(), 2);
+		assert_eq!(members.count_lefts(Index::from(12u64)).unwrap(), 0);
+	}
+
+	#[test]
+	fn relinking_is_a_no_op() {
+		let env = test_env::build(|b| b.with::<Members>());
+		link(&env, &[(1, 10), (1, 10)]);
+		link(&env, &[(1, 10)]);
+		assert_eq!(rights_of(&env, 1), [10]);
+		assert_eq!(lefts_of(&env, 10), [1]);
+	}
+
+	#[test]
+	fn unlinks_both_ways() {
+		let env = test_env::build(|b| b.with::<Members>());
+		link(&env, &[(1, 10), (1, 11), (2, 10), (2, 11), (3, 11)]);
+		env.write_blocking(|tx| {
+			let members = Members::get(tx);
+			assert!(members.unlink(Index::from(1u64), Index::from(10u64))?);
+			assert!(!members.unlink(Index::from(1u64), Index::from(10u64))?);
+			assert_eq!(members.unlink_left(Index::from(2u64))?, 2);
+			assert_eq!(members.unlink_right(Index::from(11u64))?, 2);
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		for left in 1..=3 { assert!(rights_of(&env, left).is_empty(), "left {left}"); }
+		for right in 10..=11 { assert!(lefts_of(&env, right).is_empty(), "right {right}"); }
+	}
+}
@@ -0,0 +1,194 @@
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, DbFlags, assoc_table::{archived_from_cursor_get, Cursor}};
+use culpa::throws;
+use std::marker::PhantomData;
+
+/// Many values per key, kept sorted by their archived bytes.
+/// Archived values are limited to 511 bytes by LMDB.
+pub struct MultiAssocTable<'tx, TX, K, V> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<(K, V)>,
+}
+
+impl<'tx, 'env: 'tx, TX, K, V> Table<'tx, 'env, TX> for MultiAssocTable<'tx, TX, K, V> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe> + 'tx,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn flags() -> enumflags2::BitFlags<DbFlags> { DbFlags::DupSort.into() }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).unwrap())
+	}
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, K, V> MultiAssocTable<'tx, RwTxn<'tx>, K, V> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+{
+	/// adds the value to the key's values, putting an already present pair is a no-op
+	#[throws]
+	pub fn put(&self, key: &K, value: &V) {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut value_bytes = rkyv::to_bytes(value)?;
+		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes)?;
+	}
+
+	/// deletes all values of the key
+	#[throws]
+	pub fn delete(&self, key: &K) -> bool {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		lmdb::del(self.tx, self.dbi, &mut key_bytes)?
+	}
+
+	#[throws]
+	pub fn delete_pair(&self, key: &K, value: &V) -> bool {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut value_bytes = rkyv::to_bytes(value)?;
+		lmdb::del_pair(self.tx, self.dbi, &mut key_bytes, &mut value_bytes)?
+	}
+
+	#[throws]
+	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi)?; }
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K, V> MultiAssocTable<'tx, TX, K, V> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe> + 'tx,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self {
+		Self { tx, dbi, _pd: PhantomData }
+	}
+
+	/// stops after the first error
+	#[throws]
+	pub fn get_all(&self, key: &K) -> impl Iterator<Item = Result<&'tx rkyv::Archived<V>, Error>> + use<'tx, 'env, TX, K, V> where
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		lmdb::Cursor::open(self.tx, self.dbi)?
+			.into_dups(&mut key_bytes)?
+			.map(|get| Ok(archived_from_cursor_get::<'tx, K, V>(get?)?.1))
+			.scan(false, |failed, res: Result<_, Error>| if *failed { None } else { *failed = res.is_err(); Some(res) })
+	}
+
+	#[throws]
+	pub fn contains(&self, key: &K, value: &V) -> bool where
+		V: for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	{
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut value_bytes = rkyv::to_bytes(value)?;
+		lmdb::Cursor::open(self.tx, self.dbi)?.get_both(&mut key_bytes, &mut value_bytes)?
+	}
+
+	#[throws]
+	pub fn count_dups(&self, key: &K) -> usize {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
+		if cursor.get_with_key(&mut key_bytes, lmdb::CursorOpFlags::SetKey)?.is_none() { return 0; }
+		cursor.count()?
+	}
+
+	/// stops after the first error
+	#[throws]
+	pub fn iter_keys(&self) -> impl Iterator<Item = Result<&'tx rkyv::Archived<K>, Error>> + use<'tx, 'env, TX, K, V> where
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{
+		Cursor::<TX, K, V>::new(lmdb::Cursor::open(self.tx, self.dbi)?, lmdb::CursorOpFlags::NextNodup)
+			.map(|res| res.map(|(key, _)| key))
+	}
+
+	/// stops after the first error
+	#[expect(clippy::iter_not_returning_iterator)]
+	#[throws]
+	pub fn iter(&self) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V> where
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{
+		Cursor::<TX, K, V>::new(lmdb::Cursor::open(self.tx, self.dbi)?, lmdb::CursorOpFlags::Next)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, MultiAssocTable, Error, test_env};
+
+	// u8s, so the archived bytes sort like the numbers
+	#[derive(DbName)]
+	#[table(MultiAssocTable<'tx, TX, u8, u8>)]
+	struct Tags;
+
+	fn env() -> test_env::TestEnv {
+		let env = test_env::build(|b| b.with::<Tags>());
+		env.write_blocking(|tx| {
+			for (key, value) in [(1, 30), (1, 10), (1, 20), (2, 10), (3, 5)] { Tags::get(tx).put(&key, &value)?; }
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		env
+	}
+
+	fn get_all(env: &test_env::TestEnv, key: u8) -> Vec<u8> {
+		Tags::get(&env.read_tx().unwrap()).get_all(&key).unwrap().map(|value| *value.unwrap()).collect()
+	}
+
+	#[test]
+	fn values_are_sorted() {
+		let env = env();
+		assert_eq!(get_all(&env, 1), [10, 20, 30]);
+		assert_eq!(get_all(&env, 2), [10]);
+		assert!(get_all(&env, 4).is_empty());
+		let tx = env.read_tx().unwrap();
+		let tags = Tags::get(&tx);
+		assert_eq!(tags.iter_keys().unwrap().map(|key| *key.unwrap()).collect::<Vec<_>>(), [1, 2, 3]);
+		assert_eq!(tags.iter().unwrap().map(|get| { let (key, value) = get.unwrap(); (*key, *value) }).collect::<Vec<_>>(), [(1, 10), (1, 20), (1, 30), (2, 10), (3, 5)]);
+	}
+
+	#[test]
+	fn contains_and_count() {
+		let env = env();
+		let tx = env.read_tx().unwrap();
+		let tags = Tags::get(&tx);
+		assert!(tags.contains(&1, &20).unwrap());
+		assert!(!tags.contains(&1, &25).unwrap());
+		assert!(!tags.contains(&4, &10).unwrap());
+		assert_eq!(tags.count_dups(&1).unwrap(), 3);
+		assert_eq!(tags.count_dups(&4).unwrap(), 0);
+	}
+
+	#[test]
+	fn put_pair_twice_is_a_no_op() {
+		let env = env();
+		env.write_blocking(|tx| Tags::get(tx).put(&1, &20)).unwrap().unwrap();
+		assert_eq!(get_all(&env, 1), [10, 20, 30]);
+	}
+
+	#[test]
+	fn deletes() {
+		let env = env();
+		env.write_blocking(|tx| {
+			let tags = Tags::get(tx);
+			assert!(tags.delete_pair(&1, &20)?);
+			assert!(!tags.delete_pair(&1, &20)?);
+			assert!(!tags.delete_pair(&4, &20)?);
+			assert!(tags.delete(&2)?);
+			assert!(!tags.delete(&2)?);
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		assert_eq!(get_all(&env, 1), [10, 30]);
+		assert!(get_all(&env, 2).is_empty());
+		assert_eq!(get_all(&env, 3), [5]);
+
+		env.write_blocking(|tx| Tags::get(tx).clear()).unwrap().unwrap();
+		assert!(Tags::get(&env.read_tx().unwrap()).iter().unwrap().next().is_none());
+	}
+}
@@ -0,0 +1,180 @@
+use crate::{Transaction, RwTxn, Table, Error, lmdb, DbFlags, sub_db_name};
+use culpa::throws;
+use batadase_index::Index;
+use std::marker::PhantomData;
+
+/// Links every child to at most one parent.
+/// Parent to children is a DupSort db, child to parent is a separate `::reverse` db.
+pub struct OneToManyTable<'tx, TX, Parent, Child> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	reverse_dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<(Parent, Child)>,
+}
+
+impl<'tx, 'env: 'tx, TX, Parent, Child> Table<'tx, 'env, TX> for OneToManyTable<'tx, TX, Parent, Child> where
+	TX: Transaction<'env>,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn flags() -> enumflags2::BitFlags<DbFlags> { DbFlags::IntegerKey | DbFlags::DupSort | DbFlags::DupFixed | DbFlags::IntegerDup }
+	fn extra_dbs(name: &'static [u8]) -> Vec<(std::borrow::Cow<'static, [u8]>, enumflags2::BitFlags<DbFlags>)> {
+		vec![(sub_db_name(name, "reverse").into(), DbFlags::IntegerKey.into())]
+	}
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).unwrap(), tx.env().db(&sub_db_name(name, "reverse")).unwrap())
+	}
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, Parent, Child> OneToManyTable<'tx, RwTxn<'tx>, Parent, Child> {
+	/// a child already linked to another parent is moved to this one
+	#[throws]
+	pub fn link(&self, parent: Index<Parent>, child: Index<Child>) {
+		let mut parent_bytes = u64::from(parent).to_ne_bytes();
+		let mut child_bytes = u64::from(child).to_ne_bytes();
+		if let Some(old_parent) = self.parent_of(child)? {
+			if old_parent == parent { return; }
+			let mut old_parent_bytes = u64::from(old_parent).to_ne_bytes();
+			lmdb::del_pair(self.tx, self.dbi, &mut old_parent_bytes, &mut child_bytes)?;
+		}
+		lmdb::put(self.tx, self.dbi, &mut parent_bytes, &mut child_bytes)?;
+		lmdb::put(self.tx, self.reverse_dbi, &mut child_bytes, &mut parent_bytes)?;
+	}
+
+	#[throws]
+	pub fn unlink(&self, parent: Index<Parent>, child: Index<Child>) -> bool {
+		let mut parent_bytes = u64::from(parent).to_ne_bytes();
+		let mut child_bytes = u64::from(child).to_ne_bytes();
+		if !lmdb::del_pair(self.tx, self.dbi, &mut parent_bytes, &mut child_bytes)? { return false; }
+		lmdb::del(self.tx, self.reverse_dbi, &mut child_bytes)?
+	}
+
+	/// returns how many children were unlinked
+	#[throws]
+	pub fn unlink_all(&self, parent: Index<Parent>) -> usize {
+		let children = self.children(parent)?.collect::<Result<Vec<_>, _>>()?;
+		for &child in &children {
+			let mut child_bytes = u64::from(child).to_ne_bytes();
+			lmdb::del(self.tx, self.reverse_dbi, &mut child_bytes)?;
+		}
+		let mut parent_bytes = u64::from(parent).to_ne_bytes();
+		lmdb::del(self.tx, self.dbi, &mut parent_bytes)?;
+		children.len()
+	}
+
+	#[throws]
+	pub fn clear(&self) {
+		lmdb::drop(self.tx, self.dbi)?;
+		lmdb::drop(self.tx, self.reverse_dbi)?;
+	}
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, Parent, Child> OneToManyTable<'tx, TX, Parent, Child> where
+	TX: Transaction<'env>,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi, reverse_dbi: lmdb_sys::MDB_dbi) -> Self {
+		Self { tx, dbi, reverse_dbi, _pd: PhantomData }
+	}
+
+	/// stops after the first error
+	#[throws]
+	pub fn children(&self, parent: Index<Parent>) -> impl Iterator<Item = Result<Index<Child>, Error>> + use<'tx, 'env, TX, Parent, Child> {
+		let mut key_bytes = u64::from(parent).to_ne_bytes();
+		lmdb::Cursor::open(self.tx, self.dbi)?
+			.into_dups(&mut key_bytes)?
+			.map(|get| Ok(Index::from(lmdb::u64_from_bytes(get?.1)?)))
+	}
+
+	#[throws]
+	pub fn count_children(&self, parent: Index<Parent>) -> usize {
+		let mut key_bytes = u64::from(parent).to_ne_bytes();
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
+		if cursor.get_with_key(&mut key_bytes, lmdb::CursorOpFlags::SetKey)?.is_none() { return 0; }
+		cursor.count()?
+	}
+
+	#[throws]
+	pub fn parent_of(&self, child: Index<Child>) -> Option<Index<Parent>> {
+		let mut child_bytes = u64::from(child).to_ne_bytes();
+		let Some(value_bytes) = lmdb::get(self.tx, self.reverse_dbi, &mut child_bytes)? else { return None; };
+		Some(Index::from(lmdb::u64_from_bytes(value_bytes)?))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, OneToManyTable, Index, Error, test_env};
+
+	struct Folder;
+	struct File;
+
+	#[derive(DbName)]
+	#[table(OneToManyTable<'tx, TX, Folder, File>)]
+	struct Contents;
+
+	fn children(env: &test_env::TestEnv, parent: u64) -> Vec<u64> {
+		let tx = env.read_tx().unwrap();
+		Contents::get(&tx).children(Index::from(parent)).unwrap().map(|child| u64::from(child.unwrap())).collect()
+	}
+
+	fn parent_of(env: &test_env::TestEnv, child: u64) -> Option<u64> {
+		Contents::get(&env.read_tx().unwrap()).parent_of(Index::from(child)).unwrap().map(u64::from)
+	}
+
+	fn link(env: &test_env::TestEnv, links: &[(u64, u64)]) {
+		env.write_blocking(|tx| {
+			for &(parent, child) in links { Contents::get(tx).link(Index::from(parent), Index::from(child))?; }
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+	}
+
+	#[test]
+	fn links() {
+		let env = test_env::build(|b| b.with::<Contents>());
+		link(&env, &[(1, 10), (1, 11), (2, 20)]);
+		assert_eq!(children(&env, 1), [10, 11]);
+		assert_eq!(children(&env, 2), [20]);
+		assert!(children(&env, 3).is_empty());
+		assert_eq!(parent_of(&env, 10), Some(1));
+		assert_eq!(parent_of(&env, 12), None);
+		let tx = env.read_tx().unwrap();
+		assert_eq!(Contents::get(&tx).count_children(Index::from(1u64)).unwrap(), 2);
+		assert_eq!(Contents::get(&tx).count_children(Index::from(3u64)).unwrap(), 0);
+	}
+
+	#[test]
+	fn relinking_moves_the_child() {
+		let env = test_env::build(|b| b.with::<Contents>());
+		link(&env, &[(1, 10), (1, 11), (2, 10)]);
+		assert_eq!(children(&env, 1), [11]);
+		assert_eq!(children(&env, 2), [10]);
+		assert_eq!(parent_of(&env, 10), Some(2));
+		// already there, so a no-op
+		link(&env, &[(2, 10)]);
+		assert_eq!(children(&env, 2), [10]);
+		assert_eq!(Contents::get(&env.read_tx().unwrap()).count_children(Index::from(1u64)).unwrap(), 1);
+	}
+
+	#[test]
+	fn unlinks() {
+		let env = test_env::build(|b| b.with::<Contents>());
+		link(&env, &[(1, 10), (1, 11), (2, 20), (2, 21)]);
+		env.write_blocking(|tx| {
+			let contents = Contents::get(tx);
+			assert!(contents.unlink(Index::from(1u64), Index::from(10u64))?);
+			assert!(!contents.unlink(Index::from(1u64), Index::from(10u64))?);
+			// linked to another parent
+			assert!(!contents.unlink(Index::from(2u64), Index::from(11u64))?);
+			assert_eq!(contents.unlink_all(Index::from(2u64))?, 2);
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		assert_eq!(children(&env, 1), [11]);
+		assert_eq!(parent_of(&env, 10), None);
+		assert_eq!(parent_of(&env, 11), Some(1));
+		assert!(children(&env, 2).is_empty());
+		assert_eq!(parent_of(&env, 20), None);
+		assert_eq!(parent_of(&env, 21), None);
+	}
+}
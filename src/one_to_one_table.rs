@@ -0,0 +1,197 @@
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, DbFlags, sub_db_name};
+use culpa::throws;
+use std::marker::PhantomData;
+
+/// Links every left to at most one right and vice versa, e.g. external ids to `Index<T>`s.
+/// Left to right is one db, right to left is a separate `::reverse` db.
+pub struct OneToOneTable<'tx, TX, L, R> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	reverse_dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<(L, R)>,
+}
+
+/// which side of the pair being linked is already linked to something else
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkConflict {
+	#[error("left is already linked to another right")] Left,
+	#[error("right is already linked to another left")] Right,
+}
+
+impl<'tx, 'env: 'tx, TX, L, R> Table<'tx, 'env, TX> for OneToOneTable<'tx, TX, L, R> where
+	TX: Transaction<'env>,
+	L: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	R: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<L>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+	rkyv::Archived<R>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn extra_dbs(name: &'static [u8]) -> Vec<(std::borrow::Cow<'static, [u8]>, enumflags2::BitFlags<DbFlags>)> {
+		vec![(sub_db_name(name, "reverse").into(), Self::flags())]
+	}
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).unwrap(), tx.env().db(&sub_db_name(name, "reverse")).unwrap())
+	}
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, L, R> OneToOneTable<'tx, RwTxn<'tx>, L, R> where
+	L: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	R: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+{
+	/// outer result is whether DB ops failed or not,
+	/// inner result is whether either side is already linked to something else,
+	/// in which case nothing is changed
+	/// linking an already linked pair is a no-op
+	#[throws]
+	pub fn link(&self, left: &L, right: &R) -> Result<(), LinkConflict> {
+		let mut left_bytes = rkyv::to_bytes(left)?;
+		let mut right_bytes = rkyv::to_bytes(right)?;
+		if let Some(existing) = lmdb::put_no_overwrite(self.tx, self.dbi, &mut left_bytes, &mut right_bytes)? {
+			return if existing == right_bytes.as_slice() { Ok(()) } else { Err(LinkConflict::Left) };
+		}
+		if lmdb::put_no_overwrite(self.tx, self.reverse_dbi, &mut right_bytes, &mut left_bytes)?.is_some() {
+			lmdb::del(self.tx, self.dbi, &mut left_bytes)?;
+			return Err(LinkConflict::Right);
+		}
+		Ok(())
+	}
+
+	#[throws]
+	pub fn unlink_left(&self, left: &L) -> bool {
+		let mut left_bytes = rkyv::to_bytes(left)?;
+		let Some(right_bytes) = lmdb::get(self.tx, self.dbi, &mut left_bytes)? else { return false; };
+		// copied because returned pointers don't survive writes to the tx
+		let mut right_bytes = right_bytes.to_vec();
+		lmdb::del(self.tx, self.reverse_dbi, &mut right_bytes)?;
+		lmdb::del(self.tx, self.dbi, &mut left_bytes)?
+	}
+
+	#[throws]
+	pub fn unlink_right(&self, right: &R) -> bool {
+		let mut right_bytes = rkyv::to_bytes(right)?;
+		let Some(left_bytes) = lmdb::get(self.tx, self.reverse_dbi, &mut right_bytes)? else { return false; };
+		// copied because returned pointers don't survive writes to the tx
+		let mut left_bytes = left_bytes.to_vec();
+		lmdb::del(self.tx, self.dbi, &mut left_bytes)?;
+		lmdb::del(self.tx, self.reverse_dbi, &mut right_bytes)?
+	}
+
+	#[throws]
+	pub fn clear(&self) {
+		lmdb::drop(self.tx, self.dbi)?;
+		lmdb::drop(self.tx, self.reverse_dbi)?;
+	}
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, L, R> OneToOneTable<'tx, TX, L, R> where
+	TX: Transaction<'env>,
+	L: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	R: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<L>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+	rkyv::Archived<R>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi, reverse_dbi: lmdb_sys::MDB_dbi) -> Self {
+		Self { tx, dbi, reverse_dbi, _pd: PhantomData }
+	}
+
+	#[throws]
+	pub fn right_of(&self, left: &L) -> Option<&'tx rkyv::Archived<R>> {
+		let mut left_bytes = rkyv::to_bytes(left)?;
+		let Some(right_bytes) = lmdb::get(self.tx, self.dbi, &mut left_bytes)? else { return None; };
+		Some(rkyv::access::<rkyv::Archived<R>, _>(right_bytes)?)
+	}
+
+	#[throws]
+	pub fn left_of(&self, right: &R) -> Option<&'tx rkyv::Archived<L>> {
+		let mut right_bytes = rkyv::to_bytes(right)?;
+		let Some(left_bytes) = lmdb::get(self.tx, self.reverse_dbi, &mut right_bytes)? else { return None; };
+		Some(rkyv::access::<rkyv::Archived<L>, _>(left_bytes)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, OneToOneTable, Error, test_env};
+	use super::LinkConflict;
+
+	#[derive(DbName)]
+	#[table(OneToOneTable<'tx, TX, u32, String>)]
+	struct Emails;
+
+	fn env() -> test_env::TestEnv { test_env::build(|b| b.with::<Emails>()) }
+
+	fn right_of(env: &test_env::TestEnv, left: u32) -> Option<String> {
+		Emails::get(&env.read_tx().unwrap()).right_of(&left).unwrap().map(|right| right.as_str().to_owned())
+	}
+
+	fn left_of(env: &test_env::TestEnv, right: &str) -> Option<u32> {
+		Emails::get(&env.read_tx().unwrap()).left_of(&right.to_owned()).unwrap().map(|left| left.to_native())
+	}
+
+	#[test]
+	fn links_both_ways() {
+		let env = env();
+		env.write_blocking(|tx| {
+			assert_eq!(Emails::get(tx).link(&1, &"a".to_owned())?, Ok(()));
+			// already linked to each other, so a no-op
+			assert_eq!(Emails::get(tx).link(&1, &"a".to_owned())?, Ok(()));
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		assert_eq!(right_of(&env, 1).as_deref(), Some("a"));
+		assert_eq!(left_of(&env, "a"), Some(1));
+		assert_eq!(right_of(&env, 2), None);
+		assert_eq!(left_of(&env, "b"), None);
+	}
+
+	#[test]
+	fn conflicts_change_nothing() {
+		let env = env();
+		env.write_blocking(|tx| {
+			let emails = Emails::get(tx);
+			emails.link(&1, &"a".to_owned())?.unwrap();
+			assert_eq!(emails.link(&1, &"b".to_owned())?, Err(LinkConflict::Left));
+			// the forward link is put first, so this one is rolled back
+			assert_eq!(emails.link(&2, &"a".to_owned())?, Err(LinkConflict::Right));
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		assert_eq!(right_of(&env, 1).as_deref(), Some("a"));
+		assert_eq!(left_of(&env, "a"), Some(1));
+		assert_eq!(left_of(&env, "b"), None);
+		assert_eq!(right_of(&env, 2), None);
+	}
+
+	#[test]
+	fn unlinks_both_ways() {
+		let env = env();
+		env.write_blocking(|tx| {
+			let emails = Emails::get(tx);
+			emails.link(&1, &"a".to_owned())?.unwrap();
+			emails.link(&2, &"b".to_owned())?.unwrap();
+			assert!(emails.unlink_left(&1)?);
+			assert!(!emails.unlink_left(&1)?);
+			assert!(emails.unlink_right(&"b".to_owned())?);
+			assert!(!emails.unlink_right(&"b".to_owned())?);
+			// both sides are free again
+			emails.link(&1, &"b".to_owned())?.unwrap();
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		assert_eq!(right_of(&env, 1).as_deref(), Some("b"));
+		assert_eq!(left_of(&env, "b"), Some(1));
+		assert_eq!(left_of(&env, "a"), None);
+		assert_eq!(right_of(&env, 2), None);
+	}
+
+	#[test]
+	fn clear() {
+		let env = env();
+		env.write_blocking(|tx| {
+			Emails::get(tx).link(&1, &"a".to_owned())?.unwrap();
+			Emails::get(tx).clear()
+		}).unwrap().unwrap();
+		assert_eq!(right_of(&env, 1), None);
+		assert_eq!(left_of(&env, "a"), None);
+	}
+}
@@ -0,0 +1,179 @@
+//! Secondary indices for [`IndexTable`](crate::IndexTable)s, kept in sync by its `put`/`delete_index`/`clear`, e.g.
+//! ```ignore
+//! struct UserByEmail;
+//! impl SecondaryIndex<User> for UserByEmail {
+//!     type Field = String;
+//!     const NAME: &'static [u8] = b"user_by_email\0";
+//!     fn field(user: &ArchivedUser) -> String { user.email.as_str().to_owned() }
+//! }
+//!
+//! #[derive(DbName)]
+//! #[table(IndexTable<'tx, TX, User, Users>)]
+//! #[indices(UserByEmail)]
+//! struct Users;
+//!
+//! Users::get(&tx).by_field::<UserByEmail>(&email)?
+//! ```
+//! Every index is its own `AssocTable<Field, Index<T>>` db, registered along with the table by `EnvBuilder::with`.
+//! Fields are unique, `IndexTable::put` fails with [`Conflict`] instead of changing anything
+//! if one of the value's fields already points to another index.
+
+use crate::{RwTxn, AssocTable, Index, RkyvSer, RkyvVal, Error, lmdb::DbFlags};
+use culpa::throws;
+
+/// a value's field is already indexed for another value
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("{index} already points this field to index {existing}")]
+pub struct Conflict {
+	/// the secondary index' db name
+	pub index: String,
+	pub existing: u64,
+}
+
+pub trait SecondaryIndex<T: rkyv::Archive> {
+	type Field: rkyv::Archive<Archived: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>> + for <'a> rkyv::Serialize<RkyvSer<'a>>;
+	const NAME: &'static [u8];
+
+	fn field(t: &rkyv::Archived<T>) -> Self::Field;
+}
+
+/// Implemented by `#[derive(DbName)]` with `#[indices(...)]`, `()` is no indices
+pub trait SecondaryIndices<T: rkyv::Archive> {
+	const EMPTY: bool = false;
+
+	fn dbs() -> Vec<(&'static [u8], enumflags2::BitFlags<DbFlags>)>;
+	fn check<'tx>(tx: &'tx RwTxn<'tx>, index: Index<T>, t: &rkyv::Archived<T>) -> Result<(), Error> where T: 'tx;
+	fn insert<'tx>(tx: &'tx RwTxn<'tx>, index: Index<T>, t: &rkyv::Archived<T>) -> Result<(), Error> where T: 'tx;
+	fn remove<'tx>(tx: &'tx RwTxn<'tx>, index: Index<T>, t: &rkyv::Archived<T>) -> Result<(), Error> where T: 'tx;
+	fn clear<'tx>(tx: &'tx RwTxn<'tx>) -> Result<(), Error> where T: 'tx;
+}
+
+impl<T: rkyv::Archive> SecondaryIndices<T> for () {
+	const EMPTY: bool = true;
+
+	fn dbs() -> Vec<(&'static [u8], enumflags2::BitFlags<DbFlags>)> { Vec::new() }
+	fn check<'tx>(_: &'tx RwTxn<'tx>, _: Index<T>, _: &rkyv::Archived<T>) -> Result<(), Error> where T: 'tx { Ok(()) }
+	fn insert<'tx>(_: &'tx RwTxn<'tx>, _: Index<T>, _: &rkyv::Archived<T>) -> Result<(), Error> where T: 'tx { Ok(()) }
+	fn remove<'tx>(_: &'tx RwTxn<'tx>, _: Index<T>, _: &rkyv::Archived<T>) -> Result<(), Error> where T: 'tx { Ok(()) }
+	fn clear<'tx>(_: &'tx RwTxn<'tx>) -> Result<(), Error> where T: 'tx { Ok(()) }
+}
+
+/// fails with [`Error::MissingTable`] if `S` isn't one of the table's `#[indices(...)]`
+#[throws]
+pub fn table<'tx, 'env: 'tx, TX, S, T>(tx: &'tx TX) -> AssocTable<'tx, TX, S::Field, Index<T>> where
+	TX: crate::Transaction<'env>,
+	S: SecondaryIndex<T>,
+	T: rkyv::Archive + 'tx,
+{
+	let Some(dbi) = tx.env().db(S::NAME) else { culpa::throw!(Error::MissingTable(name::<S, T>())); };
+	AssocTable::build(tx, dbi)
+}
+
+fn name<S: SecondaryIndex<T>, T: rkyv::Archive>() -> String {
+	String::from_utf8_lossy(S::NAME.strip_suffix(b"\0").unwrap_or(S::NAME)).into_owned()
+}
+
+pub fn db<S: SecondaryIndex<T>, T: rkyv::Archive>() -> (&'static [u8], enumflags2::BitFlags<DbFlags>) {
+	(S::NAME, enumflags2::BitFlags::empty())
+}
+
+/// fails with [`Conflict`] if the field already points to another index
+#[throws]
+pub fn check<'tx, S: SecondaryIndex<T>, T: rkyv::Archive + 'tx>(tx: &'tx RwTxn<'tx>, index: Index<T>, t: &rkyv::Archived<T>) {
+	let Some(existing) = table::<_, S, T>(tx)?.get(&S::field(t))?.map(Index::from) else { return; };
+	if existing != index {
+		culpa::throw!(Conflict { index: name::<S, T>(), existing: u64::from(existing) });
+	}
+}
+
+#[throws]
+pub fn insert<'tx, S: SecondaryIndex<T>, T: rkyv::Archive + 'tx>(tx: &'tx RwTxn<'tx>, index: Index<T>, t: &rkyv::Archived<T>) {
+	table::<_, S, T>(tx)?.put(&S::field(t), &index)?;
+}
+
+/// only removes the field if it still points to `index`
+#[throws]
+pub fn remove<'tx, S: SecondaryIndex<T>, T: rkyv::Archive + 'tx>(tx: &'tx RwTxn<'tx>, index: Index<T>, t: &rkyv::Archived<T>) {
+	let table = table::<_, S, T>(tx)?;
+	let field = S::field(t);
+	if table.get(&field)?.is_some_and(|x| Index::from(x) == index) {
+		table.delete(&field)?;
+	}
+}
+
+#[throws]
+pub fn clear<'tx, S: SecondaryIndex<T>, T: rkyv::Archive + 'tx>(tx: &'tx RwTxn<'tx>) {
+	table::<_, S, T>(tx)?.clear()?;
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, IndexTable, Index, Error, test_env};
+	use super::{SecondaryIndex, Conflict};
+
+	#[derive(rkyv::Archive, rkyv::Serialize)]
+	struct User { email: String }
+
+	struct ByEmail;
+	impl SecondaryIndex<User> for ByEmail {
+		type Field = String;
+		const NAME: &'static [u8] = b"by_email\0";
+		fn field(user: &ArchivedUser) -> String { user.email.as_str().to_owned() }
+	}
+
+	#[derive(DbName)]
+	#[table(IndexTable<'tx, TX, User, Users>)]
+	#[indices(ByEmail)]
+	struct Users;
+
+	fn user(email: &str) -> User { User { email: email.to_owned() } }
+
+	// not in `Users`' indices
+	struct ByLength;
+	impl SecondaryIndex<User> for ByLength {
+		type Field = u32;
+		const NAME: &'static [u8] = b"by_length\0";
+		fn field(user: &ArchivedUser) -> u32 { user.email.len() as u32 }
+	}
+
+	#[test]
+	fn taken_field_conflicts_without_changes() {
+		let env = test_env::build(|b| b.with::<Users>());
+		env.write_blocking(|tx| {
+			let users = Users::get(tx);
+			users.put(Index::from(1u64), &user("x"))?;
+			let conflict = users.put(Index::from(2u64), &user("x")).unwrap_err();
+			assert!(matches!(conflict, Error::IndexConflict(Conflict { existing: 1, .. })), "{conflict}");
+			assert!(users.get(Index::from(2u64))?.is_none());
+			assert!(!users.delete_index(Index::from(2u64))?);
+			assert_eq!(users.by_field::<ByEmail>(&"x".to_owned())?, Some(Index::from(1u64)));
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+	}
+
+	#[test]
+	fn changed_field_frees_the_old_one() {
+		let env = test_env::build(|b| b.with::<Users>());
+		env.write_blocking(|tx| {
+			let users = Users::get(tx);
+			users.put(Index::from(1u64), &user("x"))?;
+			users.put(Index::from(1u64), &user("x"))?;
+			users.put(Index::from(1u64), &user("y"))?;
+			users.put(Index::from(2u64), &user("x"))?;
+			assert_eq!(users.by_field::<ByEmail>(&"x".to_owned())?, Some(Index::from(2u64)));
+			assert_eq!(users.by_field::<ByEmail>(&"y".to_owned())?, Some(Index::from(1u64)));
+			users.delete_index(Index::from(2u64))?;
+			assert_eq!(users.by_field::<ByEmail>(&"x".to_owned())?, None);
+			assert_eq!(users.by_field::<ByEmail>(&"y".to_owned())?, Some(Index::from(1u64)));
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+	}
+
+	#[test]
+	fn unregistered_index_is_an_error() {
+		let env = test_env::build(|b| b.with::<Users>());
+		let tx = env.read_tx().unwrap();
+		let missing = Users::get(&tx).by_field::<ByLength>(&1).unwrap_err();
+		assert!(matches!(&missing, Error::MissingTable(name) if name == "by_length"), "{missing}");
+	}
+}
@@ -0,0 +1,34 @@
+use crate::{Env, Error, SharedEnv, env::EnvBuilder};
+
+// an env in a temp dir that's removed along with it
+pub(crate) struct TestEnv {
+	pub(crate) env: Env,
+	_dir: tempfile::TempDir,
+}
+
+impl TestEnv {
+	// closes the env, keeping its files around for another one
+	pub(crate) fn into_dir(self) -> tempfile::TempDir { self._dir }
+
+	// for the writes that need a `'static` env
+	pub(crate) fn shared(self) -> (SharedEnv, tempfile::TempDir) { (self.env.into(), self._dir) }
+}
+
+pub(crate) fn path(dir: &tempfile::TempDir) -> std::ffi::CString {
+	std::ffi::CString::new(dir.path().to_str().unwrap()).unwrap()
+}
+
+impl std::ops::Deref for TestEnv {
+	type Target = Env;
+	fn deref(&self) -> &Env { &self.env }
+}
+
+pub(crate) fn build(builder: impl FnOnce(EnvBuilder) -> EnvBuilder) -> TestEnv {
+	try_build(builder).unwrap()
+}
+
+pub(crate) fn try_build(builder: impl FnOnce(EnvBuilder) -> EnvBuilder) -> Result<TestEnv, Error> {
+	let dir = tempfile::tempdir().unwrap();
+	let env = builder(Env::builder()?).build(&path(&dir))?;
+	Ok(TestEnv { env, _dir: dir })
+}
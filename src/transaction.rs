@@ -14,16 +14,29 @@ use crate::{DbName, Error, lmdb};
 pub struct RoTxn<'env> {
 	pub(super) raw: *mut lmdb_sys::MDB_txn,
 	pub(super) env: &'env super::Env,
+	// in the env's registry of open read txns, which is only kept with read txn warnings on
+	pub(super) id: Option<u64>,
+	pub(super) started: std::time::Instant,
+}
+
+/// a reset `RoTxn`, see [`RoTxn::reset`]
+pub struct ParkedRoTxn<'env> {
+	raw: *mut lmdb_sys::MDB_txn,
+	env: &'env super::Env,
 }
 
 pub struct RwTxn<'env> {
 	pub(super) raw: *mut lmdb_sys::MDB_txn,
 	pub(super) env: &'env super::Env,
+	// set when a write hits MapFull, even if the job swallows the error
+	pub(super) map_full: std::sync::atomic::AtomicBool,
 }
 
 /// it is Sync + Send since you can't close a db after you open it
 unsafe impl Sync for RoTxn<'_> {}
 unsafe impl Send for RoTxn<'_> {}
+unsafe impl Sync for ParkedRoTxn<'_> {}
+unsafe impl Send for ParkedRoTxn<'_> {}
 unsafe impl Sync for RwTxn<'_> {}
 unsafe impl Send for RwTxn<'_> {}
 
@@ -57,13 +70,15 @@ impl RoTxn {
 
 pub trait Transaction<'env>: Sized {
 	fn raw(&self) -> *mut lmdb_sys::MDB_txn;
-	fn env(&self) -> &'env super::Env;
+	/// only the read methods, writes go through [`crate::Env`] itself
+	fn env(&self) -> &'env super::ReadOnlyEnv;
 	#[throws]
 	fn commit(self) {
-		lmdb::txn_commit(self.raw())?;
+		let raw = self.raw();
 		// internally it is literally `let _ = ManuallyDrop::new(x);`
-		// basically we're just avoiding Drop
+		// basically we're just avoiding Drop, since mdb_txn_commit frees the tx even if it fails
 		std::mem::forget(self);
+		lmdb::txn_commit(raw)?;
 	}
 	fn abort(self) {
 		// runs Drop, which does mdb_txn_abort
@@ -73,12 +88,133 @@ pub trait Transaction<'env>: Sized {
 
 impl<'env> Transaction<'env> for RoTxn<'env> {
 	fn raw(&self) -> *mut lmdb_sys::MDB_txn { self.raw }
-	fn env(&self) -> &'env super::Env { self.env }
+	fn env(&self) -> &'env super::ReadOnlyEnv { self.env.as_read_only() }
+	// Drop is avoided like in the default, but the env still has to know the tx is closed
+	#[throws]
+	fn commit(self) {
+		let (raw, env, id, started) = (self.raw, self.env, self.id, self.started);
+		std::mem::forget(self);
+		let res = lmdb::txn_commit(raw);
+		env.read_txn_closed(id, started);
+		res?;
+	}
 }
 impl<'env> Transaction<'env> for RwTxn<'env> {
 	fn raw(&self) -> *mut lmdb_sys::MDB_txn { self.raw }
-	fn env(&self) -> &'env super::Env { self.env }
+	fn env(&self) -> &'env super::ReadOnlyEnv { self.env.as_read_only() }
+}
+
+impl<'env> RoTxn<'env> {
+	pub fn started(&self) -> std::time::Instant { self.started }
+	pub fn age(&self) -> std::time::Duration { self.started.elapsed() }
+
+	/// Releases the snapshot but keeps the reader slot, so [`ParkedRoTxn::renew`] is cheaper than `Env::read_tx`.
+	/// See [`crate::env::EnvBuilder::read_txn_pool`] to have `Env::read_tx` do this by itself.
+	pub fn reset(self) -> ParkedRoTxn<'env> {
+		let (raw, env, id, started) = (self.raw, self.env, self.id, self.started);
+		std::mem::forget(self);
+		lmdb::txn_reset(raw);
+		env.read_txn_closed(id, started);
+		ParkedRoTxn { raw, env }
+	}
+}
+
+impl<'env> ParkedRoTxn<'env> {
+	/// a fresh snapshot, as if from `Env::read_tx`
+	#[throws]
+	pub fn renew(self) -> RoTxn<'env> {
+		let (raw, env) = (self.raw, self.env);
+		std::mem::forget(self);
+		env.renew_read_tx(raw)?
+	}
+}
+
+impl<'env> RwTxn<'env> {
+	/// Runs the job in a child transaction, which is committed into this one if the job succeeds
+	/// and aborted on its own if it fails, leaving this transaction as it was before the call.
+	///
+	/// This transaction (and tables or cursors from it) must not be used until the job returns.
+	///
+	/// outer result is whether DB ops failed or not,
+	/// inner result is whether the job failed or not
+	#[throws]
+	pub fn nested<Res, Err>(&self, job: impl FnOnce(&RwTxn<'env>) -> Result<Res, Err>) -> Result<Res, Err> {
+		let child = RwTxn { raw: lmdb::txn_begin(self.env.raw_env, self.raw, 0)?, env: self.env, map_full: Default::default() };
+		let res = job(&child);
+		if child.hit_map_full() { self.map_full.store(true, std::sync::atomic::Ordering::Relaxed); }
+		if res.is_ok() {
+			child.commit()?;
+		} else {
+			child.abort();
+		}
+		res
+	}
+
+	// `Transaction::commit`, but with the only kind of error it can fail with
+	#[throws(lmdb::Error)]
+	pub(super) fn commit_lmdb(self) {
+		let raw = self.raw;
+		std::mem::forget(self);
+		lmdb::txn_commit(raw)?;
+	}
+
+	pub(super) fn hit_map_full(&self) -> bool { self.map_full.load(std::sync::atomic::Ordering::Relaxed) }
+
+	pub(super) fn note_map_full<T>(&self, res: Result<T, lmdb::Error>) -> Result<T, lmdb::Error> {
+		if let Err(lmdb::Error::MapFull) = res { self.map_full.store(true, std::sync::atomic::Ordering::Relaxed); }
+		res
+	}
 }
 
-impl Drop for RoTxn<'_> { fn drop(&mut self) { unsafe { lmdb_sys::mdb_txn_abort(self.raw); } } }
+impl Drop for RoTxn<'_> {
+	fn drop(&mut self) {
+		self.env.recycle_read_tx(self.raw);
+		self.env.read_txn_closed(self.id, self.started);
+	}
+}
+impl Drop for ParkedRoTxn<'_> { fn drop(&mut self) { unsafe { lmdb_sys::mdb_txn_abort(self.raw); } } }
 impl Drop for RwTxn<'_> { fn drop(&mut self) { unsafe { lmdb_sys::mdb_txn_abort(self.raw); } } }
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, Table, AssocTable, Error, test_env};
+
+	#[derive(DbName)]
+	#[table(AssocTable<'tx, TX, u8, u8>)]
+	struct Numbers;
+
+	#[test]
+	fn nested_commit_is_seen_by_the_parent() {
+		let env = test_env::build(|b| b.with::<Numbers>());
+		env.write_blocking(|tx| {
+			Numbers::get(tx).put(&1, &1)?;
+			tx.nested(|child| {
+				assert!(Numbers::get(child).get(&1)?.is_some());
+				Numbers::get(child).put(&2, &2)
+			})??;
+			assert_eq!(Numbers::get(tx).entries()?, 2);
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		assert_eq!(Numbers::get(&env.read_tx().unwrap()).entries().unwrap(), 2);
+	}
+
+	#[test]
+	fn nested_abort_leaves_the_parent_as_it_was() {
+		let env = test_env::build(|b| b.with::<Numbers>());
+		env.write_blocking(|tx| {
+			Numbers::get(tx).put(&1, &1)?;
+			let res = tx.nested(|child| {
+				Numbers::get(child).put(&2, &2).unwrap();
+				Numbers::get(child).delete(&1).unwrap();
+				Err::<(), _>("failed")
+			})?;
+			assert_eq!(res, Err("failed"));
+			let numbers = Numbers::get(tx);
+			assert!(numbers.get(&1)?.is_some());
+			assert!(numbers.get(&2)?.is_none());
+			Ok::<_, Error>(())
+		}).unwrap().unwrap();
+		let tx = env.read_tx().unwrap();
+		assert_eq!(Numbers::get(&tx).entries().unwrap(), 1);
+	}
+}